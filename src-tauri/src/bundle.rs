@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor, Read, Write};
+
+use crate::crypto;
+use crate::error::BrewError;
+use crate::storage::sha256_hex;
+
+// A secure bundle is a zip of the database and `metadata.json`, encrypted
+// as a whole with `crypto::encrypt`
+const DATABASE_ENTRY: &str = "database.db";
+const METADATA_ENTRY: &str = "metadata.json";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleMetadata {
+    pub format: u32,
+    pub file_name: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub created_at: Option<u64>,
+    pub app_version: String,
+}
+
+#[derive(Serialize)]
+pub struct OpenedBundle {
+    pub data: Vec<u8>,
+    pub metadata: BundleMetadata,
+}
+
+fn archive_error(e: impl std::fmt::Display) -> BrewError {
+    BrewError::Io(io::Error::other(e.to_string()))
+}
+
+fn malformed(reason: &str) -> BrewError {
+    BrewError::InvalidDatabase(format!("not a valid brewcode bundle: {}", reason))
+}
+
+pub fn seal(
+    database: &[u8],
+    file_name: String,
+    created_at: Option<u64>,
+    passphrase: &str,
+) -> Result<Vec<u8>, BrewError> {
+    let metadata = BundleMetadata {
+        format: FORMAT_VERSION,
+        file_name,
+        sha256: sha256_hex(database),
+        size_bytes: database.len() as u64,
+        created_at,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(METADATA_ENTRY, options)
+        .map_err(archive_error)?;
+    serde_json::to_writer_pretty(&mut zip, &metadata).map_err(archive_error)?;
+    zip.start_file(DATABASE_ENTRY, options)
+        .map_err(archive_error)?;
+    zip.write_all(database)?;
+    let archive = zip.finish().map_err(archive_error)?.into_inner();
+
+    Ok(crypto::encrypt(&archive, passphrase)?)
+}
+
+// Decrypt a bundle and check the database against the checksum recorded
+// when it was sealed
+pub fn open(bundle: &[u8], passphrase: &str) -> Result<OpenedBundle, BrewError> {
+    if !crypto::is_encrypted(bundle) {
+        return Err(malformed("missing encryption header"));
+    }
+    let archive = crypto::decrypt(bundle, passphrase)?;
+    let mut zip =
+        zip::ZipArchive::new(Cursor::new(archive)).map_err(|e| malformed(&e.to_string()))?;
+
+    let metadata: BundleMetadata = {
+        let entry = zip
+            .by_name(METADATA_ENTRY)
+            .map_err(|_| malformed("metadata.json is missing"))?;
+        serde_json::from_reader(entry).map_err(|e| malformed(&e.to_string()))?
+    };
+    if metadata.format > FORMAT_VERSION {
+        return Err(malformed("it was made by a newer version of brewcode"));
+    }
+
+    let mut data = Vec::with_capacity(metadata.size_bytes as usize);
+    zip.by_name(DATABASE_ENTRY)
+        .map_err(|_| malformed("database.db is missing"))?
+        .read_to_end(&mut data)?;
+    if sha256_hex(&data) != metadata.sha256 {
+        return Err(malformed("the database doesn't match its checksum"));
+    }
+
+    Ok(OpenedBundle { data, metadata })
+}
@@ -0,0 +1,184 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::storage;
+
+// The JSON files in the app config directory. Once the directory turns out
+// to be missing or unwritable, values are kept in memory instead for the rest
+// of the session, so preferences keep working until the app quits.
+#[derive(Default)]
+pub struct ConfigStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    dir: Option<PathBuf>,
+    in_memory: bool,
+    // None marks a value removed this session, whatever is left on disk
+    memory: HashMap<String, Option<Vec<u8>>>,
+    // Why we fell back to memory, until `take_unavailable` hands it out
+    notice: Option<String>,
+}
+
+impl Inner {
+    fn fall_back(&mut self, reason: String) {
+        if !self.in_memory {
+            self.in_memory = true;
+            self.notice = Some(reason);
+        }
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        if self.in_memory {
+            return None;
+        }
+        self.dir.as_ref().map(|dir| dir.join(name))
+    }
+}
+
+impl ConfigStore {
+    // Use `dir`, creating it if needed; an error means memory only
+    pub fn init<E: std::fmt::Display>(&self, dir: Result<PathBuf, E>) {
+        let dir = dir.map_err(|e| e.to_string()).and_then(|dir| {
+            fs::create_dir_all(&dir)
+                .map(|_| dir)
+                .map_err(|e| e.to_string())
+        });
+        let mut inner = self.inner.lock().unwrap();
+        match dir {
+            Ok(dir) => inner.dir = Some(dir),
+            Err(reason) => inner.fall_back(reason),
+        }
+    }
+
+    // Where `name` lives on disk; None while running from memory
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        self.inner.lock().unwrap().file(name)
+    }
+
+    // The value stored as `name` this session, or else the one on disk. Files
+    // are still read after falling back, since reading may work when writing
+    // doesn't.
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let contents = match inner.memory.get(name) {
+            Some(contents) => contents.clone()?,
+            None => fs::read(inner.dir.as_ref()?.join(name)).ok()?,
+        };
+        serde_json::from_slice(&contents).ok()
+    }
+
+    // Persist `value` as `name`. If the write fails the store switches to
+    // memory, keeping `value` there, and the error is returned for logging.
+    pub fn store<T: Serialize>(&self, name: &str, value: &T) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(value)?;
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(path) = inner.file(name) {
+            let written = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| storage::atomic_write(&path, &contents));
+            match written {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    inner.fall_back(e.to_string());
+                    inner.memory.insert(name.to_string(), Some(contents));
+                    return Err(e);
+                }
+            }
+        }
+        inner.memory.insert(name.to_string(), Some(contents));
+        Ok(())
+    }
+
+    // Forget `name`, in memory and on disk. The file is deleted even after
+    // falling back, since deleting may work when writing doesn't; if it can't
+    // be, `load` still won't return it. A file that's already gone is fine.
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.in_memory {
+            inner.memory.insert(name.to_string(), None);
+        } else {
+            inner.memory.remove(name);
+        }
+        match inner
+            .dir
+            .as_ref()
+            .map(|dir| fs::remove_file(dir.join(name)))
+        {
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Why the config directory can't be used, the first time this is asked
+    // after it failed; None otherwise
+    pub fn take_unavailable(&self) -> Option<String> {
+        self.inner.lock().unwrap().notice.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unavailable_dir_keeps_config_in_memory() {
+        let config = ConfigStore::default();
+        config.init(Err::<PathBuf, _>("no config directory on this platform"));
+
+        assert!(config.path("settings.json").is_none());
+        config.store("settings.json", &vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            config.load::<Vec<u32>>("settings.json"),
+            Some(vec![1, 2, 3])
+        );
+        config.remove("settings.json").unwrap();
+        assert_eq!(config.load::<Vec<u32>>("settings.json"), None);
+
+        assert!(config.take_unavailable().is_some());
+        assert!(config.take_unavailable().is_none());
+
+        // A real dir that stops taking writes still has files from before
+        let dir = storage::tests::scratch_dir("config-memory");
+        fs::create_dir(dir.join("blocked.json")).unwrap();
+        fs::write(dir.join("recovery.json"), b"\"stale\"").unwrap();
+        let config = ConfigStore::default();
+        config.init(Ok::<_, io::Error>(dir.clone()));
+        assert!(config.store("blocked.json", &1).is_err());
+        assert!(config.path("recovery.json").is_none());
+
+        assert_eq!(
+            config.load::<String>("recovery.json").as_deref(),
+            Some("stale")
+        );
+        config.remove("recovery.json").unwrap();
+        assert_eq!(config.load::<String>("recovery.json"), None);
+        assert!(!dir.join("recovery.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dir_under_a_file_falls_back_to_memory() {
+        let root = storage::tests::scratch_dir("config-blocked");
+        let blocker = root.join("blocker");
+        fs::write(&blocker, b"not a folder").unwrap();
+        let config = ConfigStore::default();
+        config.init(Ok::<_, io::Error>(blocker.join("config")));
+
+        config.store("recent_files.json", &"brewcode.db").unwrap();
+        assert_eq!(
+            config.load::<String>("recent_files.json").as_deref(),
+            Some("brewcode.db")
+        );
+        assert!(config.take_unavailable().is_some());
+        assert!(config.take_unavailable().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
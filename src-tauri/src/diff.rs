@@ -0,0 +1,87 @@
+use crate::error::BrewError;
+use crate::sqlite;
+use rusqlite::Connection;
+use serde::Serialize;
+
+// Row counts of a table present on both sides
+#[derive(Serialize)]
+pub struct TableDelta {
+    pub name: String,
+    pub disk_rows: u64,
+    pub memory_rows: u64,
+    pub delta: i64,
+}
+
+// Table-level differences between the file on disk and the frontend's bytes.
+// Rows are only counted, not compared. From `compare`, the first file plays
+// the disk side and the second the memory side.
+#[derive(Serialize)]
+pub struct DbDiff {
+    pub added_tables: Vec<String>,
+    pub removed_tables: Vec<String>,
+    pub tables: Vec<TableDelta>,
+}
+
+// Open one side of the comparison, naming it in the error if it isn't a database
+fn open_side(data: &[u8], side: &str) -> Result<Connection, BrewError> {
+    let invalid = |reason: String| BrewError::InvalidDatabase(format!("{}: {}", side, reason));
+    if !crate::storage::has_sqlite_header(data) {
+        return Err(invalid("missing SQLite header".to_string()));
+    }
+    let conn = sqlite::open_bytes(data).map_err(|e| invalid(e.to_string()))?;
+    // The header alone doesn't prove the pages are readable
+    sqlite::user_tables(&conn).map_err(|e| invalid(e.to_string()))?;
+    Ok(conn)
+}
+
+fn table_counts(conn: &Connection) -> rusqlite::Result<Vec<(String, u64)>> {
+    sqlite::user_tables(conn)?
+        .into_iter()
+        .map(|name| {
+            let count = sqlite::row_count(conn, &name)?;
+            Ok((name, count))
+        })
+        .collect()
+}
+
+pub fn diff(disk: &[u8], memory: &[u8]) -> Result<DbDiff, BrewError> {
+    diff_sides(disk, "the file on disk", memory, "the unsaved data")
+}
+
+// Differences between two files; tables only `b` has count as added
+pub fn compare(a: &[u8], b: &[u8]) -> Result<DbDiff, BrewError> {
+    diff_sides(a, "the first file", b, "the second file")
+}
+
+fn diff_sides(
+    disk: &[u8],
+    disk_label: &str,
+    memory: &[u8],
+    memory_label: &str,
+) -> Result<DbDiff, BrewError> {
+    let disk = table_counts(&open_side(disk, disk_label)?)?;
+    let memory = table_counts(&open_side(memory, memory_label)?)?;
+
+    let mut diff = DbDiff {
+        added_tables: Vec::new(),
+        removed_tables: Vec::new(),
+        tables: Vec::new(),
+    };
+    for (name, disk_rows) in &disk {
+        match memory.iter().find(|(other, _)| other == name) {
+            Some((_, memory_rows)) => diff.tables.push(TableDelta {
+                name: name.clone(),
+                disk_rows: *disk_rows,
+                memory_rows: *memory_rows,
+                delta: *memory_rows as i64 - *disk_rows as i64,
+            }),
+            None => diff.removed_tables.push(name.clone()),
+        }
+    }
+    for (name, _) in &memory {
+        if !disk.iter().any(|(other, _)| other == name) {
+            diff.added_tables.push(name.clone());
+        }
+    }
+    Ok(diff)
+}
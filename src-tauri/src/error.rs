@@ -0,0 +1,369 @@
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+// Errors returned by every command. Serialized as `{ kind, message }` so the
+// frontend can branch on a stable `kind` and still show a readable message.
+// Some variants add their own fields next to those two.
+#[derive(Debug)]
+pub enum BrewError {
+    Cancelled,
+    Io(std::io::Error),
+    NoPathSet,
+    InvalidDatabase(String),
+    PermissionDenied(String),
+    BackupNotFound(u8),
+    ReadOnly,
+    Sqlite(rusqlite::Error),
+    NoLiveConnection,
+    CheckpointIncomplete,
+    NothingToUndo,
+    NothingToRedo,
+    ConfirmationRequired,
+    InsufficientSpace { required: u64, available: u64 },
+    WrongPassphrase { path: Option<String> },
+    PassphraseRequired,
+    Encryption(String),
+    LockedByOther { pid: u32, since: Option<u64> },
+    UnknownTable(String),
+    InvalidCsv(String),
+    MissingReference { table: String, referenced: String },
+    DatabaseTooNew { version: u32, supported: u32 },
+    Timeout { seconds: u64 },
+    FileManager(String),
+    InvalidHint(String),
+    FileIdentityChanged,
+    ForbiddenLocation(String),
+    SchemaMismatch { table: String, reason: String },
+    MergeConflict { table: String, rows: u64 },
+    InvalidFileName(String),
+    TargetExists(String),
+    InvalidPragma(String),
+    InvalidSaveData(String),
+    InvalidUrl(String),
+    Network(String),
+    DownloadTooLarge { limit: u64 },
+    DowngradeNotSupported { from: u32, to: u32 },
+    ReferenceUnavailable(String),
+    InvalidQuery(String),
+    QueryTimeout { seconds: u64 },
+    StatementFailed { index: usize, reason: String },
+    UnsupportedLocation(String),
+    DiskFull,
+    PayloadTooLarge { limit: u64 },
+    UnknownTemplate(String),
+    FileTooLarge { size: u64, limit: u64 },
+    // `modified` is in unix millis; None when the file is gone or has no mtime
+    ExternalChangeDetected { modified: Option<u64> },
+    UnknownSlot(String),
+    AlreadyOpen(String),
+    LiveConnectionOpen,
+    TargetIsCurrent,
+}
+
+impl BrewError {
+    // Stable identifier for the frontend; never change an existing value
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BrewError::Cancelled => "cancelled",
+            BrewError::Io(_) => "io",
+            BrewError::NoPathSet => "no_path_set",
+            BrewError::InvalidDatabase(_) => "invalid_database",
+            BrewError::PermissionDenied(_) => "permission_denied",
+            BrewError::BackupNotFound(_) => "backup_not_found",
+            BrewError::ReadOnly => "read_only",
+            BrewError::Sqlite(_) => "sqlite",
+            BrewError::NoLiveConnection => "no_live_connection",
+            BrewError::CheckpointIncomplete => "checkpoint_incomplete",
+            BrewError::NothingToUndo => "nothing_to_undo",
+            BrewError::NothingToRedo => "nothing_to_redo",
+            BrewError::ConfirmationRequired => "confirmation_required",
+            BrewError::InsufficientSpace { .. } => "insufficient_space",
+            BrewError::WrongPassphrase { .. } => "wrong_passphrase",
+            BrewError::PassphraseRequired => "passphrase_required",
+            BrewError::Encryption(_) => "encryption",
+            BrewError::LockedByOther { .. } => "locked_by_other",
+            BrewError::UnknownTable(_) => "unknown_table",
+            BrewError::InvalidCsv(_) => "invalid_csv",
+            BrewError::MissingReference { .. } => "missing_reference",
+            BrewError::DatabaseTooNew { .. } => "database_too_new",
+            BrewError::Timeout { .. } => "timeout",
+            BrewError::FileManager(_) => "file_manager",
+            BrewError::InvalidHint(_) => "invalid_hint",
+            BrewError::FileIdentityChanged => "file_identity_changed",
+            BrewError::ForbiddenLocation(_) => "forbidden_location",
+            BrewError::SchemaMismatch { .. } => "schema_mismatch",
+            BrewError::MergeConflict { .. } => "merge_conflict",
+            BrewError::InvalidFileName(_) => "invalid_file_name",
+            BrewError::TargetExists(_) => "target_exists",
+            BrewError::InvalidPragma(_) => "invalid_pragma",
+            BrewError::InvalidSaveData(_) => "invalid_save_data",
+            BrewError::InvalidUrl(_) => "invalid_url",
+            BrewError::Network(_) => "network",
+            BrewError::DownloadTooLarge { .. } => "download_too_large",
+            BrewError::DowngradeNotSupported { .. } => "downgrade_not_supported",
+            BrewError::ReferenceUnavailable(_) => "reference_unavailable",
+            BrewError::InvalidQuery(_) => "invalid_query",
+            BrewError::QueryTimeout { .. } => "query_timeout",
+            BrewError::StatementFailed { .. } => "statement_failed",
+            BrewError::UnsupportedLocation(_) => "unsupported_location",
+            BrewError::DiskFull => "disk_full",
+            BrewError::PayloadTooLarge { .. } => "payload_too_large",
+            BrewError::UnknownTemplate(_) => "unknown_template",
+            BrewError::FileTooLarge { .. } => "file_too_large",
+            BrewError::ExternalChangeDetected { .. } => "external_change_detected",
+            BrewError::UnknownSlot(_) => "unknown_slot",
+            BrewError::AlreadyOpen(_) => "already_open",
+            BrewError::LiveConnectionOpen => "live_connection_open",
+            BrewError::TargetIsCurrent => "target_is_current",
+        }
+    }
+}
+
+impl fmt::Display for BrewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrewError::Cancelled => write!(f, "Operation cancelled"),
+            BrewError::Io(e) => write!(f, "File operation failed: {}", e),
+            BrewError::NoPathSet => write!(f, "No database path set. Use 'Save As' first."),
+            BrewError::InvalidDatabase(reason) => write!(f, "Not a valid database: {}", reason),
+            BrewError::PermissionDenied(reason) => write!(f, "Permission denied: {}", reason),
+            BrewError::BackupNotFound(index) => write!(f, "Backup {} does not exist", index),
+            BrewError::ReadOnly => write!(f, "The database file is read-only. Use 'Save As' to save a copy."),
+            BrewError::Sqlite(e) => write!(f, "Database error: {}", e),
+            BrewError::NoLiveConnection => write!(f, "No live database connection is open"),
+            BrewError::CheckpointIncomplete => {
+                write!(f, "Another connection is still reading the database; try again")
+            }
+            BrewError::NothingToUndo => write!(f, "Nothing to undo"),
+            BrewError::NothingToRedo => write!(f, "Nothing to redo"),
+            BrewError::ConfirmationRequired => write!(f, "This action must be confirmed"),
+            BrewError::InsufficientSpace { required, available } => write!(
+                f,
+                "Not enough disk space: {} bytes needed, {} bytes available",
+                required, available
+            ),
+            BrewError::WrongPassphrase { .. } => write!(f, "Wrong passphrase, or the file has been tampered with"),
+            BrewError::PassphraseRequired => write!(f, "This database is encrypted; a passphrase is required"),
+            BrewError::Encryption(reason) => write!(f, "Encryption failed: {}", reason),
+            BrewError::LockedByOther { pid, .. } => {
+                write!(f, "The database is already open in another brewcode window (process {})", pid)
+            }
+            BrewError::UnknownTable(name) => write!(f, "No table named '{}'", name),
+            BrewError::InvalidCsv(reason) => write!(f, "Could not read CSV: {}", reason),
+            BrewError::MissingReference { table, referenced } => write!(
+                f,
+                "Table '{}' references '{}', which isn't included; add it or leave '{}' out",
+                table, referenced, table
+            ),
+            BrewError::DatabaseTooNew { version, supported } => write!(
+                f,
+                "This database uses schema version {}, but this version of brewcode only supports up to {}. Please update brewcode.",
+                version, supported
+            ),
+            BrewError::Timeout { seconds } => write!(
+                f,
+                "The file operation didn't finish within {} seconds; the drive may be unavailable",
+                seconds
+            ),
+            BrewError::FileManager(reason) => write!(f, "Could not open the file manager: {}", reason),
+            BrewError::InvalidHint(reason) => write!(f, "Invalid password hint: {}", reason),
+            BrewError::FileIdentityChanged => write!(
+                f,
+                "A different file has been moved to this path since it was opened. Use 'Save As', or save again to overwrite it."
+            ),
+            BrewError::ForbiddenLocation(dir) => write!(
+                f,
+                "Saving inside {} is blocked because it's a system or app folder; choose another location",
+                dir
+            ),
+            BrewError::SchemaMismatch { table, reason } => {
+                write!(f, "Table '{}' can't be merged: {}", table, reason)
+            }
+            BrewError::MergeConflict { table, rows } => write!(
+                f,
+                "Table '{}' has {} rows whose primary key already exists here; nothing was merged",
+                table, rows
+            ),
+            BrewError::InvalidFileName(reason) => write!(f, "Invalid file name: {}", reason),
+            BrewError::TargetExists(path) => write!(f, "{} already exists", path),
+            BrewError::InvalidPragma(reason) => write!(f, "Invalid setting: {}", reason),
+            BrewError::InvalidSaveData(reason) => {
+                write!(f, "Refusing to save data that isn't a valid database: {}", reason)
+            }
+            BrewError::InvalidUrl(reason) => write!(f, "Invalid URL: {}", reason),
+            BrewError::Network(reason) => write!(f, "Download failed: {}", reason),
+            BrewError::DownloadTooLarge { limit } => {
+                write!(f, "The file is larger than the {} byte download limit", limit)
+            }
+            BrewError::DowngradeNotSupported { from, to } => write!(
+                f,
+                "The step from schema version {} back to {} can't be reversed",
+                from, to
+            ),
+            BrewError::ReferenceUnavailable(reason) => {
+                write!(f, "Reference database unavailable: {}", reason)
+            }
+            BrewError::InvalidQuery(reason) => write!(f, "Invalid query: {}", reason),
+            BrewError::QueryTimeout { seconds } => {
+                write!(f, "The query was stopped after running for {} seconds", seconds)
+            }
+            BrewError::StatementFailed { index, reason } => write!(
+                f,
+                "Statement {} failed, so none of the changes were made: {}",
+                index + 1,
+                reason
+            ),
+            BrewError::UnsupportedLocation(url) => write!(
+                f,
+                "brewcode can't read or write {} directly; choose a location on this device's file system",
+                url
+            ),
+            BrewError::DiskFull => write!(
+                f,
+                "The disk filled up before the file was fully written; free some space and try again"
+            ),
+            BrewError::PayloadTooLarge { limit } => write!(
+                f,
+                "The database is larger than the {} byte limit for sharing as text",
+                limit
+            ),
+            BrewError::UnknownTemplate(id) => write!(f, "No template named '{}'", id),
+            BrewError::FileTooLarge { size, limit } => write!(
+                f,
+                "The file is {} bytes, over the {} byte limit for opening; check it's the right file",
+                size, limit
+            ),
+            BrewError::ExternalChangeDetected { .. } => write!(
+                f,
+                "The file was changed by another program since it was opened or last saved"
+            ),
+            BrewError::UnknownSlot(id) => write!(f, "No document is open in slot {}", id),
+            BrewError::AlreadyOpen(path) => write!(f, "{} is already open in another tab", path),
+            BrewError::LiveConnectionOpen => write!(
+                f,
+                "The file has a live connection open; save through it first so its changes aren't lost"
+            ),
+            BrewError::TargetIsCurrent => write!(
+                f,
+                "That's the database you have open; choose a different file to export to"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BrewError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BrewError::Io(e) => Some(e),
+            BrewError::Sqlite(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// ENOSPC, or ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL on Windows.
+// `ErrorKind::StorageFull` covers these but needs a newer Rust than we support.
+fn is_disk_full(e: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const CODES: &[i32] = &[28];
+    #[cfg(windows)]
+    const CODES: &[i32] = &[39, 112];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    e.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+impl From<std::io::Error> for BrewError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::PermissionDenied => BrewError::PermissionDenied(e.to_string()),
+            _ if is_disk_full(&e) => BrewError::DiskFull,
+            _ => BrewError::Io(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BrewError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::NotADatabase) => BrewError::InvalidDatabase(e.to_string()),
+            Some(rusqlite::ErrorCode::DiskFull) => BrewError::DiskFull,
+            _ => BrewError::Sqlite(e),
+        }
+    }
+}
+
+impl From<crate::crypto::CryptoError> for BrewError {
+    fn from(e: crate::crypto::CryptoError) -> Self {
+        use crate::crypto::CryptoError;
+        match e {
+            CryptoError::WrongPassphrase => BrewError::WrongPassphrase { path: None },
+            CryptoError::Malformed => {
+                BrewError::InvalidDatabase("the file is not an encrypted brewcode database".to_string())
+            }
+            CryptoError::Failed(reason) => BrewError::Encryption(reason),
+        }
+    }
+}
+
+impl Serialize for BrewError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        if let BrewError::InsufficientSpace { required, available } = self {
+            map.serialize_entry("required", required)?;
+            map.serialize_entry("available", available)?;
+        }
+        if let BrewError::LockedByOther { pid, since } = self {
+            map.serialize_entry("pid", pid)?;
+            map.serialize_entry("since", since)?;
+        }
+        if let BrewError::MissingReference { table, referenced } = self {
+            map.serialize_entry("table", table)?;
+            map.serialize_entry("referenced", referenced)?;
+        }
+        if let BrewError::DatabaseTooNew { version, supported } = self {
+            map.serialize_entry("version", version)?;
+            map.serialize_entry("supported", supported)?;
+        }
+        if let BrewError::Timeout { seconds } = self {
+            map.serialize_entry("seconds", seconds)?;
+        }
+        if let BrewError::SchemaMismatch { table, .. } = self {
+            map.serialize_entry("table", table)?;
+        }
+        if let BrewError::MergeConflict { table, rows } = self {
+            map.serialize_entry("table", table)?;
+            map.serialize_entry("rows", rows)?;
+        }
+        if let BrewError::DownloadTooLarge { limit } = self {
+            map.serialize_entry("limit", limit)?;
+        }
+        if let BrewError::DowngradeNotSupported { from, to } = self {
+            map.serialize_entry("from", from)?;
+            map.serialize_entry("to", to)?;
+        }
+        if let BrewError::QueryTimeout { seconds } = self {
+            map.serialize_entry("seconds", seconds)?;
+        }
+        if let BrewError::StatementFailed { index, .. } = self {
+            map.serialize_entry("index", index)?;
+        }
+        if let BrewError::PayloadTooLarge { limit } = self {
+            map.serialize_entry("limit", limit)?;
+        }
+        if let BrewError::FileTooLarge { size, limit } = self {
+            map.serialize_entry("size", size)?;
+            map.serialize_entry("limit", limit)?;
+        }
+        if let BrewError::ExternalChangeDetected { modified } = self {
+            map.serialize_entry("modified", modified)?;
+        }
+        if let BrewError::WrongPassphrase { path } = self {
+            map.serialize_entry("path", path)?;
+        }
+        map.end()
+    }
+}
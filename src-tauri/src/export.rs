@@ -0,0 +1,486 @@
+use base64::Engine;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::{self, Seek};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::BrewError;
+use crate::sqlite::{attach_read_only, open_read_only, quote_ident, user_tables};
+
+// SQL literal for one value, written so SQLite reads back the same type
+fn sql_literal(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(n) => n.to_string(),
+        // `{:?}` keeps the `.0` on whole numbers so they stay REAL
+        ValueRef::Real(f) if f.is_nan() => "NULL".to_string(),
+        ValueRef::Real(f) if f.is_infinite() => {
+            if f > 0.0 { "9e999" } else { "-9e999" }.to_string()
+        }
+        ValueRef::Real(f) => format!("{:?}", f),
+        ValueRef::Text(bytes) => {
+            format!("'{}'", String::from_utf8_lossy(bytes).replace('\'', "''"))
+        }
+        ValueRef::Blob(bytes) => {
+            let mut hex = String::with_capacity(bytes.len() * 2 + 3);
+            hex.push_str("X'");
+            for byte in bytes {
+                let _ = write!(hex, "{:02X}", byte);
+            }
+            hex.push('\'');
+            hex
+        }
+    }
+}
+
+// Exports check this between rows, so a cancelled one stops promptly
+fn check_cancelled(cancelled: &AtomicBool) -> Result<(), BrewError> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(BrewError::Cancelled);
+    }
+    Ok(())
+}
+
+// `ORDER BY` giving `table`'s rows a stable order: its primary key, or every
+// column in turn when it has none
+pub fn row_order(conn: &Connection, table: &str) -> rusqlite::Result<String> {
+    let mut stmt =
+        conn.prepare("SELECT name FROM pragma_table_info(?1) WHERE pk > 0 ORDER BY pk")?;
+    let key = stmt
+        .query_map([table], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let terms = if key.is_empty() {
+        let width: i64 = conn.query_row(
+            "SELECT count(*) FROM pragma_table_info(?1)",
+            [table],
+            |row| row.get(0),
+        )?;
+        (1..=width).map(|i| i.to_string()).collect::<Vec<_>>()
+    } else {
+        key.iter().map(|name| quote_ident(name)).collect()
+    };
+    Ok(format!(" ORDER BY {}", terms.join(", ")))
+}
+
+fn write_inserts(
+    conn: &Connection,
+    table: &str,
+    deterministic: bool,
+    out: &mut String,
+    cancelled: &AtomicBool,
+) -> Result<(), BrewError> {
+    let order = if deterministic {
+        row_order(conn, table)?
+    } else {
+        String::new()
+    };
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}{}", quote_ident(table), order))?;
+    let column_count = stmt.column_count();
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        check_cancelled(cancelled)?;
+        let values = (0..column_count)
+            .map(|i| row.get_ref(i).map(sql_literal))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let _ = writeln!(out, "INSERT INTO {} VALUES({});", quote_ident(table), values.join(","));
+    }
+    Ok(())
+}
+
+// Plain-text dump of the whole database: every table's `CREATE TABLE` and
+// rows in table-name order, then indexes, views and triggers in the order
+// they were created. Running the script on an empty database recreates it.
+// With `deterministic`, rows are sorted so the same data always gives the
+// same script, for dumps kept under version control. `progress` gets the
+// tables written so far and the total after each table.
+pub fn dump_sql(
+    conn: &Connection,
+    deterministic: bool,
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<String, BrewError> {
+    let mut out = String::from("PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n");
+
+    let tables = user_tables(conn)?;
+    for (done, table) in tables.iter().enumerate() {
+        let create: String = conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )?;
+        let _ = writeln!(out, "{};", create);
+        write_inserts(conn, table, deterministic, &mut out, cancelled)?;
+        progress(done + 1, tables.len());
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT sql FROM sqlite_master \
+         WHERE type IN ('index', 'view', 'trigger') AND sql IS NOT NULL \
+         ORDER BY rowid",
+    )?;
+    let statements = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for statement in statements {
+        let _ = writeln!(out, "{};", statement);
+    }
+
+    out.push_str("COMMIT;\n");
+    Ok(out)
+}
+
+// JSON for one value; blobs become base64 strings and NaN/infinity null
+pub fn json_value(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(n) => n.into(),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, Into::into),
+        ValueRef::Text(bytes) => String::from_utf8_lossy(bytes).into(),
+        ValueRef::Blob(bytes) => base64::engine::general_purpose::STANDARD.encode(bytes).into(),
+    }
+}
+
+// All rows of `table` as a JSON array of objects keyed by column name. Rows
+// are written into the output as they're read rather than collected first.
+// `table` must already be checked against `user_tables`.
+pub fn table_json(conn: &Connection, table: &str) -> rusqlite::Result<String> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote_ident(table)))?;
+    let keys: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|name| serde_json::Value::from(name).to_string())
+        .collect();
+    let mut rows = stmt.query([])?;
+
+    let mut out = String::from("[");
+    let mut first_row = true;
+    while let Some(row) = rows.next()? {
+        if !first_row {
+            out.push(',');
+        }
+        first_row = false;
+
+        out.push('{');
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}:{}", key, json_value(row.get_ref(i)?));
+        }
+        out.push('}');
+    }
+    out.push(']');
+    Ok(out)
+}
+
+// Rows of `table` as newline-delimited JSON, one object per line, written to
+// `writer` as they're read. Returns the number of rows. `table` must already
+// be checked against `user_tables`.
+pub fn table_ndjson<W: io::Write>(
+    conn: &Connection,
+    table: &str,
+    mut writer: W,
+    cancelled: &AtomicBool,
+) -> Result<u64, BrewError> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote_ident(table)))?;
+    // Built by hand rather than through a JSON map, which would sort the keys
+    let keys: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|name| serde_json::Value::from(name).to_string())
+        .collect();
+    let mut rows = stmt.query([])?;
+
+    let mut count = 0;
+    let mut line = String::new();
+    while let Some(row) = rows.next()? {
+        check_cancelled(cancelled)?;
+        line.clear();
+        line.push('{');
+        for (i, key) in keys.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            let _ = write!(line, "{}:{}", key, json_value(row.get_ref(i)?));
+        }
+        line.push_str("}\n");
+        writer.write_all(line.as_bytes())?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+// A new database holding only `tables` from the file at `source`: their
+// schema, rows, indexes and triggers. Fails if a kept table has a foreign key
+// into one that's left out, since the copy would be unusable with foreign
+// keys enforced. `progress` is called as in `dump_sql`.
+pub fn subset(
+    source: &Path,
+    tables: &[String],
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<Vec<u8>, BrewError> {
+    let source_conn = open_read_only(source)?;
+    let existing = user_tables(&source_conn)?;
+    for table in tables {
+        if !existing.contains(table) {
+            return Err(BrewError::UnknownTable(table.clone()));
+        }
+        let mut stmt =
+            source_conn.prepare("SELECT DISTINCT \"table\" FROM pragma_foreign_key_list(?1)")?;
+        let referenced = stmt
+            .query_map([table], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if let Some(missing) = referenced
+            .into_iter()
+            .find(|name| !tables.iter().any(|t| t.eq_ignore_ascii_case(name)))
+        {
+            return Err(BrewError::MissingReference {
+                table: table.clone(),
+                referenced: missing,
+            });
+        }
+    }
+
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+        | OpenFlags::SQLITE_OPEN_CREATE
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = Connection::open_with_flags(":memory:", flags)?;
+    // Tables are copied one at a time, so a child can arrive before its parent
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    attach_read_only(&conn, source, "source")?;
+
+    for (done, table) in tables.iter().enumerate() {
+        check_cancelled(cancelled)?;
+        let create: String = source_conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get(0),
+        )?;
+        conn.execute_batch(&create)?;
+        conn.execute(
+            &format!("INSERT INTO main.{0} SELECT * FROM source.{0}", quote_ident(table)),
+            [],
+        )?;
+        progress(done + 1, tables.len());
+    }
+
+    // Indexes and triggers of the kept tables, in creation order
+    let mut stmt = source_conn.prepare(
+        "SELECT tbl_name, sql FROM sqlite_master \
+         WHERE type IN ('index', 'trigger') AND sql IS NOT NULL \
+         ORDER BY rowid",
+    )?;
+    let extras = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (table, sql) in extras {
+        if tables.contains(&table) {
+            conn.execute_batch(&sql)?;
+        }
+    }
+
+    conn.execute_batch("DETACH DATABASE source")?;
+    let data = conn.serialize(rusqlite::MAIN_DB)?;
+    Ok(data.to_vec())
+}
+
+// One CSV file in an `all_csv_zip` archive
+#[derive(Serialize)]
+pub struct ZipManifestEntry {
+    pub table: String,
+    pub file: String,
+    pub rows: u64,
+}
+
+// Text for one CSV field; NULL is empty and blobs are base64
+fn csv_field(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(n) => n.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).to_string(),
+        ValueRef::Blob(bytes) => base64::engine::general_purpose::STANDARD.encode(bytes),
+    }
+}
+
+// `<table>.csv`, with characters file systems reject replaced and a number
+// added if two tables end up with the same name
+fn csv_file_name(table: &str, used: &mut HashSet<String>) -> String {
+    let stem: String = table
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut name = format!("{}.csv", stem);
+    let mut n = 2;
+    while !used.insert(name.to_lowercase()) {
+        name = format!("{} ({}).csv", stem, n);
+        n += 1;
+    }
+    name
+}
+
+fn archive_error(e: impl std::fmt::Display) -> BrewError {
+    BrewError::Io(io::Error::other(e.to_string()))
+}
+
+// Write every user table as `<table>.csv` into a zip on `writer`, plus a
+// `manifest.json` of tables and row counts. Rows are streamed straight into
+// the archive, so memory use doesn't grow with the database. `progress` is
+// called as in `dump_sql`.
+pub fn all_csv_zip<W: io::Write + Seek>(
+    conn: &Connection,
+    writer: W,
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<Vec<ZipManifestEntry>, BrewError> {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(writer);
+    let mut used = HashSet::new();
+    let mut manifest = Vec::new();
+
+    let tables = user_tables(conn)?;
+    let total = tables.len();
+    for (done, table) in tables.into_iter().enumerate() {
+        let file = csv_file_name(&table, &mut used);
+        zip.start_file(file.as_str(), options).map_err(archive_error)?;
+
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {}", quote_ident(&table)))?;
+        let column_count = stmt.column_count();
+        let mut csv = csv::Writer::from_writer(&mut zip);
+        csv.write_record(stmt.column_names()).map_err(archive_error)?;
+
+        let mut rows = stmt.query([])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            check_cancelled(cancelled)?;
+            let fields = (0..column_count)
+                .map(|i| row.get_ref(i).map(csv_field))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            csv.write_record(&fields).map_err(archive_error)?;
+            count += 1;
+        }
+        csv.flush()?;
+        drop(csv);
+
+        manifest.push(ZipManifestEntry {
+            table,
+            file,
+            rows: count,
+        });
+        progress(done + 1, total);
+    }
+
+    zip.start_file("manifest.json", options).map_err(archive_error)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest).map_err(archive_error)?;
+    zip.finish().map_err(archive_error)?.flush()?;
+    Ok(manifest)
+}
+
+// Table stamped into read-only snapshots; its presence marks the file as one
+pub const SNAPSHOT_TABLE: &str = "_brewcode_snapshot";
+
+// Where and when a snapshot was taken, and the SHA-256 of the source bytes
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub source_path: String,
+    pub created_at: Option<u64>,
+    pub sha256: String,
+}
+
+// A copy of the database `data` with a `SNAPSHOT_TABLE` row recording `info`.
+// `data` itself is never changed.
+pub fn snapshot(data: &[u8], info: &SnapshotInfo) -> Result<Vec<u8>, BrewError> {
+    let conn = crate::sqlite::open_bytes_mut(data)?;
+    let table = quote_ident(SNAPSHOT_TABLE);
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS {0}; \
+         CREATE TABLE {0} (source_path TEXT NOT NULL, created_at INTEGER, sha256 TEXT NOT NULL);",
+        table
+    ))?;
+    conn.execute(
+        &format!(
+            "INSERT INTO {} (source_path, created_at, sha256) VALUES (?1, ?2, ?3)",
+            table
+        ),
+        rusqlite::params![
+            info.source_path,
+            info.created_at.map(|ms| ms as i64),
+            info.sha256
+        ],
+    )?;
+    Ok(conn.serialize(rusqlite::MAIN_DB)?.to_vec())
+}
+
+// The snapshot stamp in `conn`, or None if it isn't a snapshot
+pub fn snapshot_info(conn: &Connection) -> rusqlite::Result<Option<SnapshotInfo>> {
+    if !user_tables(conn)?.iter().any(|name| name == SNAPSHOT_TABLE) {
+        return Ok(None);
+    }
+    let sql = format!(
+        "SELECT source_path, created_at, sha256 FROM {} LIMIT 1",
+        quote_ident(SNAPSHOT_TABLE)
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(SnapshotInfo {
+            source_path: row.get(0)?,
+            created_at: row.get::<_, Option<i64>>(1)?.map(|ms| ms as u64),
+            sha256: row.get(2)?,
+        })),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database(rows: &[&str]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE recipes (name TEXT PRIMARY KEY, abv REAL);
+             CREATE TABLE notes (recipe TEXT, body TEXT);",
+        )
+        .unwrap();
+        for row in rows {
+            conn.execute_batch(row).unwrap();
+        }
+        conn
+    }
+
+    fn dump(conn: &Connection) -> String {
+        dump_sql(conn, true, &AtomicBool::new(false), |_, _| {}).unwrap()
+    }
+
+    #[test]
+    fn deterministic_dumps_ignore_insert_order() {
+        let rows = [
+            "INSERT INTO recipes VALUES ('stout', 6.5)",
+            "INSERT INTO recipes VALUES ('ale', 4.2)",
+            "INSERT INTO recipes VALUES ('lager', 5.0)",
+            "INSERT INTO notes VALUES ('stout', 'roast barley')",
+            "INSERT INTO notes VALUES ('ale', 'dry hop')",
+            "INSERT INTO notes VALUES ('ale', 'cold crash')",
+        ];
+        let mut reversed = rows;
+        reversed.reverse();
+
+        let first = dump(&database(&rows));
+        assert_eq!(first, dump(&database(&reversed)));
+        assert_eq!(first, dump(&database(&rows)));
+    }
+}
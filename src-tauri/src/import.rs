@@ -0,0 +1,210 @@
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use crate::error::BrewError;
+use crate::sqlite::{quote_ident, table_columns};
+
+// Only the first errors are kept; a bad file can fail on every row
+const MAX_REPORTED_ERRORS: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportReport {
+    pub inserted: u64,
+    pub skipped: u64,
+    pub errors: Vec<String>,
+    // True when too many rows failed and nothing was kept
+    pub rolled_back: bool,
+}
+
+// SQLite's column affinity, worked out from the declared type the same way
+// SQLite does it (section 3.1 of the datatype docs)
+#[derive(Clone, Copy)]
+enum Affinity {
+    Integer,
+    Text,
+    Blob,
+    Real,
+    Numeric,
+}
+
+impl Affinity {
+    fn of(declared: &str) -> Affinity {
+        let declared = declared.to_ascii_uppercase();
+        if declared.contains("INT") {
+            Affinity::Integer
+        } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| declared.contains(t)) {
+            Affinity::Text
+        } else if declared.is_empty() || declared.contains("BLOB") {
+            Affinity::Blob
+        } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| declared.contains(t)) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    // Convert one CSV field. Empty fields become NULL except in text columns.
+    // Integer and real columns reject anything that isn't a number so a typo
+    // in a spreadsheet doesn't end up stored as text.
+    fn coerce(self, field: &str) -> Result<Value, String> {
+        let trimmed = field.trim();
+        if trimmed.is_empty() && !matches!(self, Affinity::Text) {
+            return Ok(Value::Null);
+        }
+
+        match self {
+            Affinity::Text | Affinity::Blob => Ok(Value::Text(field.to_string())),
+            Affinity::Integer => match (trimmed.parse::<i64>(), trimmed.parse::<f64>()) {
+                (Ok(n), _) => Ok(Value::Integer(n)),
+                (_, Ok(f)) if f.fract() == 0.0 && f.abs() < 9.2e18 => Ok(Value::Integer(f as i64)),
+                (_, Ok(f)) => Ok(Value::Real(f)),
+                _ => Err(format!("'{}' is not a number", field)),
+            },
+            Affinity::Real => trimmed
+                .parse::<f64>()
+                .map(Value::Real)
+                .map_err(|_| format!("'{}' is not a number", field)),
+            Affinity::Numeric => Ok(match (trimmed.parse::<i64>(), trimmed.parse::<f64>()) {
+                (Ok(n), _) => Value::Integer(n),
+                (_, Ok(f)) => Value::Real(f),
+                _ => Value::Text(field.to_string()),
+            }),
+        }
+    }
+}
+
+struct Report {
+    inserted: u64,
+    skipped: u64,
+    errors: Vec<String>,
+}
+
+impl Report {
+    fn fail(&mut self, line: u64, message: impl std::fmt::Display) {
+        self.skipped += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(format!("line {}: {}", line, message));
+        }
+    }
+}
+
+// Insert the rows of a CSV into `table` inside one transaction. With a header
+// row, fields are matched to columns by name (case-insensitive) and unknown
+// fields are ignored; without one they map to columns in order. Rows that
+// fail to convert or insert are skipped, and if more than
+// `max_failed_rows` fail the whole import is rolled back.
+pub fn import_csv<R: io::Read>(
+    conn: &mut Connection,
+    table: &str,
+    reader: R,
+    has_header: bool,
+    max_failed_rows: u64,
+) -> Result<ImportReport, BrewError> {
+    let columns = table_columns(conn, table)?;
+    if columns.is_empty() {
+        return Err(BrewError::UnknownTable(table.to_string()));
+    }
+
+    let mut csv = csv::ReaderBuilder::new()
+        .has_headers(has_header)
+        .flexible(true)
+        .from_reader(reader);
+
+    // (CSV field index, column index) pairs
+    let mapping: Vec<(usize, usize)> = if has_header {
+        let headers = csv
+            .headers()
+            .map_err(|e| BrewError::InvalidCsv(e.to_string()))?
+            .clone();
+        headers
+            .iter()
+            .enumerate()
+            .filter_map(|(field, name)| {
+                columns
+                    .iter()
+                    .position(|column| column.name.eq_ignore_ascii_case(name.trim()))
+                    .map(|column| (field, column))
+            })
+            .collect()
+    } else {
+        (0..columns.len()).map(|i| (i, i)).collect()
+    };
+    if mapping.is_empty() {
+        return Err(BrewError::InvalidCsv(format!(
+            "none of the CSV columns match a column of {}",
+            table
+        )));
+    }
+
+    let affinities: Vec<Affinity> = mapping
+        .iter()
+        .map(|&(_, column)| Affinity::of(&columns[column].data_type))
+        .collect();
+    let names: Vec<String> = mapping
+        .iter()
+        .map(|&(_, column)| quote_ident(&columns[column].name))
+        .collect();
+    let placeholders: Vec<String> = (1..=mapping.len()).map(|i| format!("?{}", i)).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_ident(table),
+        names.join(", "),
+        placeholders.join(", ")
+    );
+
+    let tx = conn.transaction()?;
+    let mut report = Report {
+        inserted: 0,
+        skipped: 0,
+        errors: Vec::new(),
+    };
+    {
+        let mut stmt = tx.prepare(&sql)?;
+        for record in csv.records() {
+            if report.skipped > max_failed_rows {
+                break;
+            }
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    let line = e.position().map_or(0, |pos| pos.line());
+                    report.fail(line, e);
+                    continue;
+                }
+            };
+            let line = record.position().map_or(0, |pos| pos.line());
+
+            let values = mapping
+                .iter()
+                .zip(&affinities)
+                .map(|(&(field, _), affinity)| match record.get(field) {
+                    Some(value) => affinity.coerce(value),
+                    None => Ok(Value::Null),
+                })
+                .collect::<Result<Vec<_>, _>>();
+            match values {
+                Ok(values) => match stmt.execute(rusqlite::params_from_iter(values)) {
+                    Ok(_) => report.inserted += 1,
+                    Err(e) => report.fail(line, e),
+                },
+                Err(message) => report.fail(line, message),
+            }
+        }
+    }
+
+    let rolled_back = report.skipped > max_failed_rows;
+    if rolled_back {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+
+    Ok(ImportReport {
+        inserted: if rolled_back { 0 } else { report.inserted },
+        skipped: report.skipped,
+        errors: report.errors,
+        rolled_back,
+    })
+}
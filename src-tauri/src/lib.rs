@@ -1,184 +1,4078 @@
-use std::sync::Mutex;
-use tauri::State;
+mod bundle;
+mod config;
+mod crypto;
+mod diff;
+mod error;
+mod export;
+mod import;
+mod lock;
+mod merge;
+mod migrations;
+mod project;
+mod recover;
+mod settings;
+mod sqlite;
+mod storage;
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager, State};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
-struct AppState {
-    current_db_path: Mutex<Option<String>>,
+use error::BrewError;
+
+struct AppState {
+    current_db_path: Mutex<Option<PathBuf>>,
+    settings: Mutex<settings::Settings>,
+    dirty: Mutex<bool>,
+    recent_files: Mutex<Vec<RecentFile>>,
+    disk_stamp: Mutex<Option<storage::FileStamp>>,
+    read_only: Mutex<bool>,
+    saved_checksum: Mutex<Option<String>>,
+    dialog_config: Mutex<DialogConfig>,
+    last_directory: Mutex<Option<PathBuf>>,
+    live_connection: Mutex<Option<rusqlite::Connection>>,
+    reference_db: Mutex<Option<PathBuf>>,
+    open_cancelled: AtomicBool,
+    export_cancelled: Arc<AtomicBool>,
+    autosave_task: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    undo_stack: Mutex<Vec<Vec<u8>>>,
+    redo_stack: Mutex<Vec<Vec<u8>>>,
+    encrypted: Mutex<bool>,
+    view_states: Mutex<HashMap<String, ViewState>>,
+    password_hints: Mutex<HashMap<String, String>>,
+    launch_path: Mutex<Option<LaunchOpen>>,
+    temp_files: Mutex<Vec<PathBuf>>,
+    forbidden_dirs: Mutex<Vec<PathBuf>>,
+    recovery: Mutex<Option<Recovery>>,
+    activity: Mutex<VecDeque<ActivityEntry>>,
+    last_error: Mutex<Option<ErrorDetails>>,
+    // Documents open besides the current one, by slot id
+    slots: Mutex<HashMap<String, DocumentState>>,
+    config: config::ConfigStore,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            current_db_path: Mutex::new(None),
+            settings: Mutex::new(settings::Settings::default()),
+            dirty: Mutex::new(false),
+            recent_files: Mutex::new(Vec::new()),
+            disk_stamp: Mutex::new(None),
+            read_only: Mutex::new(false),
+            saved_checksum: Mutex::new(None),
+            dialog_config: Mutex::new(DialogConfig::default()),
+            last_directory: Mutex::new(None),
+            live_connection: Mutex::new(None),
+            reference_db: Mutex::new(None),
+            open_cancelled: AtomicBool::new(false),
+            export_cancelled: Arc::new(AtomicBool::new(false)),
+            autosave_task: Mutex::new(None),
+            undo_stack: Mutex::new(Vec::new()),
+            redo_stack: Mutex::new(Vec::new()),
+            encrypted: Mutex::new(false),
+            view_states: Mutex::new(HashMap::new()),
+            password_hints: Mutex::new(HashMap::new()),
+            launch_path: Mutex::new(None),
+            temp_files: Mutex::new(Vec::new()),
+            forbidden_dirs: Mutex::new(storage::system_dirs()),
+            recovery: Mutex::new(None),
+            activity: Mutex::new(VecDeque::new()),
+            last_error: Mutex::new(None),
+            slots: Mutex::new(HashMap::new()),
+            config: config::ConfigStore::default(),
+        }
+    }
+}
+
+// File type shown in the open/save dialogs. The first extension is the one
+// enforced on save.
+#[derive(Clone, Serialize, Deserialize)]
+struct DialogConfig {
+    extensions: Vec<String>,
+    filter_label: String,
+    default_file_name: String,
+    // Also offer "All Files" when opening, for databases with other extensions
+    #[serde(default)]
+    add_all_files_filter: bool,
+}
+
+impl Default for DialogConfig {
+    fn default() -> Self {
+        Self {
+            extensions: vec!["db".to_string()],
+            filter_label: "SQLite Database".to_string(),
+            default_file_name: "brewcode.db".to_string(),
+            add_all_files_filter: false,
+        }
+    }
+}
+
+// An entry in the recent-files list
+#[derive(Clone, Serialize, Deserialize)]
+struct RecentFile {
+    path: String,
+    last_opened: Option<u64>,
+}
+
+// Recent files were once stored as bare paths
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredRecentFile {
+    Entry(RecentFile),
+    Path(String),
+}
+
+// A recent file with what the dashboard shows about it
+#[derive(Serialize)]
+struct RecentFileInfo {
+    path: String,
+    path_display: String,
+    exists: bool,
+    size_bytes: Option<u64>,
+    valid_sqlite: bool,
+    last_opened: Option<u64>,
+}
+
+// Unsaved bytes cached by `mark_dirty`, offered back after a crash through
+// `recover-available`
+#[derive(Clone, Serialize, Deserialize)]
+struct Recovery {
+    path: Option<String>,
+    data_path: PathBuf,
+    saved_at: Option<u64>,
+    size_bytes: u64,
+}
+
+// The most recent failure, kept for a "copy error details" button
+#[derive(Clone, Serialize)]
+struct ErrorDetails {
+    at: Option<u64>,
+    operation: String,
+    path: String,
+    kind: &'static str,
+    message: String,
+}
+
+// One line of the in-app activity log. `outcome` is "ok", "cancelled" or the
+// error kind.
+#[derive(Clone, Serialize)]
+struct ActivityEntry {
+    at: Option<u64>,
+    kind: String,
+    path: String,
+    outcome: &'static str,
+    message: Option<String>,
+}
+
+// Where the user was in a database, restored when they reopen it
+#[derive(Clone, Serialize, Deserialize)]
+struct ViewState {
+    table: Option<String>,
+    row_offset: u64,
+}
+
+// Payload of `open-on-launch`: the file to open and, when the launch named
+// one that exists in it, the table or view to show first
+#[derive(Clone, Serialize)]
+struct LaunchOpen {
+    path: String,
+    table: Option<String>,
+}
+
+// Payload of `open-on-launch-failed`
+#[derive(Clone, Serialize)]
+struct LaunchFailure {
+    path: String,
+    kind: &'static str,
+    message: String,
+}
+
+const OPEN_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+const MAX_ACTIVITY_ENTRIES: usize = 200;
+// Downloads end up in memory, so refuse anything bigger than this
+const MAX_DOWNLOAD_BYTES: u64 = 512 * 1024 * 1024;
+const DOWNLOAD_CONNECT_TIMEOUT_SECS: u64 = 15;
+// A download that receives nothing for this long is abandoned
+const DOWNLOAD_READ_TIMEOUT_SECS: u64 = 30;
+// Databases shared as base64 text get no bigger than this before encoding
+const MAX_BASE64_BYTES: u64 = 4 * 1024 * 1024;
+// Size of the header at the start of every SQLite file
+const SQLITE_HEADER_BYTES: u64 = 100;
+// `path_display` of a document that hasn't been saved yet
+const UNSAVED_DISPLAY_NAME: &str = "Untitled";
+// Saves smaller than this finish too quickly for progress events to help
+const SAVE_PROGRESS_MIN_BYTES: u64 = 8 * 1024 * 1024;
+
+#[derive(Clone, Serialize)]
+struct OpenProgress {
+    bytes_read: u64,
+    total_bytes: u64,
+}
+
+// Payload of `export-progress`: tables exported so far out of `total`
+#[derive(Clone, Serialize)]
+struct ExportProgress {
+    processed: u64,
+    total: u64,
+}
+
+// Payload of `save-started`, `save-progress` and `save-finished`
+#[derive(Clone, Serialize)]
+struct SaveProgress {
+    path: String,
+    bytes_written: u64,
+    total_bytes: u64,
+}
+
+// Payload of `save-failed`
+#[derive(Clone, Serialize)]
+struct SaveFailure {
+    path: String,
+    kind: &'static str,
+    message: String,
+}
+
+const RECENT_FILES_FILE: &str = "recent_files.json";
+const MAX_RECENT_FILES: usize = 10;
+const LAST_DIRECTORY_FILE: &str = "last_directory.json";
+const VIEW_STATES_FILE: &str = "view_states.json";
+const PASSWORD_HINTS_FILE: &str = "password_hints.json";
+const SETTINGS_FILE: &str = "settings.json";
+// Held `reopen_last_on_launch` before settings.json existed
+const LEGACY_REOPEN_LAST_FILE: &str = "reopen_last_on_launch.json";
+const JOURNAL_FILE: &str = "recovery.json";
+const JOURNAL_DATA_FILE: &str = "recovery.db";
+const MAX_HINT_CHARS: usize = 200;
+const REFERENCE_ALIAS: &str = "ref";
+const MAX_QUERY_ROWS: usize = 10_000;
+const QUERY_TIMEOUT_SECS: u64 = 10;
+// Bundled resource listing the templates in the same folder
+const TEMPLATES_MANIFEST: &str = "templates/templates.json";
+const LOG_FILE_NAME: &str = "brewcode";
+const LOG_MAX_BYTES: u128 = 5 * 1024 * 1024;
+
+// Location of a file in the app config directory; None when the directory
+// is unavailable and config lives in memory
+fn config_file(app: &tauri::AppHandle, name: &str) -> Option<PathBuf> {
+    app.state::<AppState>().config.path(name)
+}
+
+fn load_config<T: DeserializeOwned>(app: &tauri::AppHandle, name: &str) -> Option<T> {
+    app.state::<AppState>().config.load(name)
+}
+
+// Persist a value to the config directory. Failures are logged, not fatal:
+// losing a preference shouldn't stop the user from saving their data, and
+// the value is still kept for this session.
+fn store_config<R: tauri::Runtime, T: Serialize>(app: &tauri::AppHandle<R>, name: &str, value: &T) {
+    if let Err(e) = app.state::<AppState>().config.store(name, value) {
+        log::warn!("Failed to write {}: {}", name, e);
+    }
+    report_config_unavailable(app);
+}
+
+// Tell the frontend, once, that preferences won't outlive this session
+fn report_config_unavailable<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
+    if let Some(reason) = app.state::<AppState>().config.take_unavailable() {
+        log::warn!("Config directory unavailable, keeping config in memory: {}", reason);
+        emit_logged(app, "config-unavailable", reason);
+    }
+}
+
+// Move `path` to the top of the recent-files list and persist it
+fn remember_recent<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AppState,
+    path: &std::path::Path,
+) {
+    let path = path_string(path);
+    let mut recent = state.recent_files.lock().unwrap();
+    recent.retain(|existing| existing.path != path);
+    recent.insert(
+        0,
+        RecentFile {
+            path,
+            last_opened: unix_millis(std::time::SystemTime::now()),
+        },
+    );
+    recent.truncate(MAX_RECENT_FILES);
+    store_config(app, RECENT_FILES_FILE, &*recent);
+}
+
+// Missing files are kept so the dashboard can offer to remove them
+fn load_recent_files(app: &tauri::AppHandle) -> Vec<RecentFile> {
+    let recent: Vec<StoredRecentFile> = load_config(app, RECENT_FILES_FILE).unwrap_or_default();
+    recent
+        .into_iter()
+        .map(|stored| match stored {
+            StoredRecentFile::Entry(entry) => entry,
+            StoredRecentFile::Path(path) => RecentFile {
+                path,
+                last_opened: None,
+            },
+        })
+        .take(MAX_RECENT_FILES)
+        .collect()
+}
+
+// Whether `path` starts with a SQLite header, reading only the header
+fn has_sqlite_header_on_disk(path: &std::path::Path) -> bool {
+    storage::read_prefix(path, storage::SQLITE_MAGIC.len() as u64)
+        .is_ok_and(|header| storage::has_sqlite_header(&header))
+}
+
+// Cache unsaved bytes so they survive a crash, if the user opted in.
+// Encrypted documents are never journaled: the cache would be plaintext.
+fn write_journal(app: &tauri::AppHandle, state: &AppState, data: &[u8]) {
+    let settings = state.settings.lock().unwrap().clone();
+    if !settings.journal_enabled || *state.encrypted.lock().unwrap() {
+        return;
+    }
+    if data.len() as u64 > settings.journal_max_bytes {
+        // Older cached bytes would be misleading next to newer edits
+        clear_journal(app);
+        return;
+    }
+    let Some(data_path) = config_file(app, JOURNAL_DATA_FILE) else {
+        return;
+    };
+
+    let written = data_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| storage::atomic_write(&data_path, data));
+    if let Err(e) = written {
+        log::warn!("Failed to write {}: {}", JOURNAL_DATA_FILE, e);
+        return;
+    }
+    let recovery = Recovery {
+        path: state
+            .current_db_path
+            .lock()
+            .unwrap()
+            .as_deref()
+            .map(path_string),
+        data_path,
+        saved_at: unix_millis(std::time::SystemTime::now()),
+        size_bytes: data.len() as u64,
+    };
+    store_config(app, JOURNAL_FILE, &recovery);
+}
+
+// Forget cached unsaved bytes once they are saved or deliberately discarded
+fn clear_journal<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
+    for name in [JOURNAL_FILE, JOURNAL_DATA_FILE] {
+        if let Err(e) = app.state::<AppState>().config.remove(name) {
+            log::warn!("Failed to remove {}: {}", name, e);
+        }
+    }
+}
+
+// A journal left behind by a session that never saved or closed cleanly
+fn load_recovery(app: &tauri::AppHandle) -> Option<Recovery> {
+    let recovery: Recovery = load_config(app, JOURNAL_FILE)?;
+    recovery.data_path.exists().then_some(recovery)
+}
+
+// Saved view states, minus entries for files that no longer exist
+fn load_view_states(app: &tauri::AppHandle) -> HashMap<String, ViewState> {
+    let mut states: HashMap<String, ViewState> =
+        load_config(app, VIEW_STATES_FILE).unwrap_or_default();
+    states.retain(|key, _| storage::path_from_key(key).exists());
+    states
+}
+
+// Saved passphrase hints, minus entries for files that no longer exist
+fn load_password_hints(app: &tauri::AppHandle) -> HashMap<String, String> {
+    let mut hints: HashMap<String, String> =
+        load_config(app, PASSWORD_HINTS_FILE).unwrap_or_default();
+    hints.retain(|key, _| storage::path_from_key(key).exists());
+    hints
+}
+
+// Remember the folder of a file we just opened or saved for the next dialog
+fn remember_directory(app: &tauri::AppHandle, state: &AppState, path: &std::path::Path) {
+    let Some(dir) = path.parent().map(std::path::Path::to_path_buf) else {
+        return;
+    };
+    let mut last = state.last_directory.lock().unwrap();
+    if last.as_ref() != Some(&dir) {
+        *last = Some(dir);
+        store_config(app, LAST_DIRECTORY_FILE, &*last);
+    }
+}
+
+// File dialog starting in the last-used folder, or Documents if that's gone
+fn base_dialog(
+    app: &tauri::AppHandle,
+    state: &AppState,
+) -> tauri_plugin_dialog::FileDialogBuilder<tauri::Wry> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let directory = state
+        .last_directory
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|dir| dir.is_dir())
+        .or_else(|| app.path().document_dir().ok());
+
+    let dialog = app.dialog().file();
+    match directory {
+        Some(dir) => dialog.set_directory(dir),
+        None => dialog,
+    }
+}
+
+// File dialog pre-filtered to the configured database type
+fn file_dialog(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    config: &DialogConfig,
+) -> tauri_plugin_dialog::FileDialogBuilder<tauri::Wry> {
+    let extensions: Vec<&str> = config.extensions.iter().map(String::as_str).collect();
+    base_dialog(app, state).add_filter(&config.filter_label, &extensions)
+}
+
+// Emit an event to the frontend; a failure only means nobody hears it
+fn emit_logged<R: tauri::Runtime, S: Serialize + Clone>(
+    app: &tauri::AppHandle<R>,
+    event: &str,
+    payload: S,
+) {
+    if let Err(e) = app.emit(event, payload) {
+        log::warn!("Failed to emit {}: {}", event, e);
+    }
+}
+
+// Update the current path, notifying the frontend only on an actual change.
+// The exact path is kept for saving; the event only carries its display form.
+fn set_current_path(app: &tauri::AppHandle, state: &AppState, path: Option<PathBuf>) {
+    let mut current = state.current_db_path.lock().unwrap();
+    if *current != path {
+        let display = path.as_deref().map(path_display);
+        *current = path;
+        if let Err(e) = app.emit("db-path-changed", display) {
+            log::warn!("Failed to emit db-path-changed: {}", e);
+        }
+    }
+}
+
+// Update the dirty flag, notifying the frontend only on an actual change
+fn set_dirty<R: tauri::Runtime>(app: &tauri::AppHandle<R>, state: &AppState, dirty: bool) {
+    let mut current = state.dirty.lock().unwrap();
+    if *current != dirty {
+        *current = dirty;
+        if let Err(e) = app.emit("db-dirty-changed", dirty) {
+            log::warn!("Failed to emit db-dirty-changed: {}", e);
+        }
+    }
+}
+
+// Path of the current database, or `NoPathSet` if nothing is open
+fn current_path(state: &AppState) -> Result<PathBuf, BrewError> {
+    state
+        .current_db_path
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(BrewError::NoPathSet)
+}
+
+// `path` as a string the frontend can pass back to a command and that config
+// files are keyed by; see `storage::path_key`
+fn path_string(path: &std::path::Path) -> String {
+    storage::path_key(path)
+}
+
+// `path` for showing to the user; may be lossy
+fn path_display(path: &std::path::Path) -> String {
+    path.display().to_string()
+}
+
+fn unix_millis(time: std::time::SystemTime) -> Option<u64> {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_millis() as u64)
+}
+
+// Remember the file's size and mtime right after we read or wrote it
+fn record_disk_stamp(state: &AppState, path: &std::path::Path) {
+    *state.disk_stamp.lock().unwrap() = storage::FileStamp::of(path).ok();
+}
+
+// App and OS folders that saves are kept out of: our own config, data,
+// cache and log folders plus the usual system locations
+fn forbidden_dirs(app: &tauri::AppHandle) -> Vec<PathBuf> {
+    let resolver = app.path();
+    let mut dirs: Vec<PathBuf> = [
+        resolver.app_config_dir(),
+        resolver.app_data_dir(),
+        resolver.app_local_data_dir(),
+        resolver.app_cache_dir(),
+        resolver.app_log_dir(),
+    ]
+    .into_iter()
+    .filter_map(Result::ok)
+    .collect();
+    dirs.extend(storage::system_dirs());
+    dirs
+}
+
+// Reject save targets inside a forbidden folder unless the user opted out
+fn ensure_safe_location(state: &AppState, path: &std::path::Path) -> Result<(), BrewError> {
+    if state.settings.lock().unwrap().allow_unsafe_location {
+        return Ok(());
+    }
+    let dirs = state.forbidden_dirs.lock().unwrap();
+    match storage::containing_dir(path, &dirs) {
+        Some(dir) => Err(BrewError::ForbiddenLocation(dir.display().to_string())),
+        None => Ok(()),
+    }
+}
+
+// Refuse to save over a file that replaced the one we opened or last saved,
+// e.g. another file renamed onto the same path. A missing file is fine:
+// saving just recreates it.
+fn ensure_same_file(state: &AppState, path: &std::path::Path) -> Result<(), BrewError> {
+    let stamp = *state.disk_stamp.lock().unwrap();
+    ensure_same_file_as(stamp, path)
+}
+
+fn ensure_same_file_as(
+    stamp: Option<storage::FileStamp>,
+    path: &std::path::Path,
+) -> Result<(), BrewError> {
+    let Some(expected) = stamp.and_then(|stamp| stamp.id) else {
+        return Ok(());
+    };
+    match storage::file_id(path) {
+        Ok(Some(id)) if id != expected => Err(BrewError::FileIdentityChanged),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Whether `path` names the current database, directly or through another
+// spelling of the same file such as a symlink
+fn is_current_file(state: &AppState, path: &std::path::Path) -> bool {
+    let Some(current) = state.current_db_path.lock().unwrap().clone() else {
+        return false;
+    };
+    if current == path {
+        return true;
+    }
+    match (storage::file_id(&current), storage::file_id(path)) {
+        (Ok(Some(a)), Ok(Some(b))) => a == b,
+        _ => false,
+    }
+}
+
+// Refuse to replace the current file with bytes from the frontend while the
+// live connection has it open: the connection would go on writing to the
+// replaced file, or apply its stale `-wal` to the new one. `save_live`
+// checkpoints and closes it.
+fn ensure_no_live_connection(state: &AppState) -> Result<(), BrewError> {
+    if state.live_connection.lock().unwrap().is_some() {
+        return Err(BrewError::LiveConnectionOpen);
+    }
+    Ok(())
+}
+
+// Exports, copies and rescues write somewhere new; only saving may replace
+// the current file, with its backups and checks
+fn ensure_not_current(state: &AppState, path: &std::path::Path) -> Result<(), BrewError> {
+    if is_current_file(state, path) {
+        return Err(BrewError::TargetIsCurrent);
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct DbPathResponse {
+    path: Option<String>,
+    path_display: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DbInfo {
+    path: Option<String>,
+    path_display: String,
+    size_bytes: u64,
+    modified: Option<u64>,
+    readonly: bool,
+    // Set when the file came from `export_readonly_snapshot`
+    snapshot: Option<export::SnapshotInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VacuumReport {
+    size_before: u64,
+    size_after: u64,
+    bytes_reclaimed: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveResponse {
+    success: bool,
+    path: Option<String>,
+    path_display: Option<String>,
+    warning: Option<String>,
+    bytes_written: Option<u64>,
+    // True when a save to a chosen path made a new file rather than replacing
+    // one; saves to the current file always replace it
+    created_new: bool,
+    // Whether the save was also copied to the `mirror_path` folder; None
+    // when no mirror is set
+    mirror_written: Option<bool>,
+}
+
+impl SaveResponse {
+    fn saved(path: &std::path::Path, bytes_written: Option<u64>) -> Self {
+        SaveResponse {
+            success: true,
+            path: Some(path_string(path)),
+            path_display: Some(path_display(path)),
+            warning: None,
+            bytes_written,
+            created_new: false,
+            mirror_written: None,
+        }
+    }
+
+    // Record the outcome of `write_mirror`; a failed mirror becomes the warning
+    fn mirrored(self, mirror: Option<Result<(), String>>) -> Self {
+        match mirror {
+            None => self,
+            Some(Ok(())) => SaveResponse {
+                mirror_written: Some(true),
+                ..self
+            },
+            Some(Err(warning)) => SaveResponse {
+                mirror_written: Some(false),
+                warning: Some(warning),
+                ..self
+            },
+        }
+    }
+}
+
+// Copy a finished save at `path` into the `mirror_path` folder, atomically
+// and under the same file name. None when no mirror is set. A failure is
+// returned as a warning rather than an error, since the save itself worked.
+fn write_mirror(state: &AppState, path: &std::path::Path) -> Option<Result<(), String>> {
+    let mirror_dir = PathBuf::from(state.settings.lock().unwrap().mirror_path.clone()?);
+    let target = mirror_dir.join(path.file_name()?);
+    let own_dir = path.parent().and_then(|dir| std::fs::canonicalize(dir).ok());
+    if own_dir.is_some() && std::fs::canonicalize(&mirror_dir).ok() == own_dir {
+        return Some(Err("The mirror folder is the file's own folder, so nothing was mirrored"
+            .to_string()));
+    }
+
+    let result = storage::copy_atomic(path, &target).map_err(BrewError::from);
+    let len = std::fs::metadata(path).map_or(0, |metadata| metadata.len() as usize);
+    log_operation(state, "mirror", &target, len, &result);
+    Some(result.map_err(|e| {
+        format!("Saved, but copying to the mirror {} failed: {}", target.display(), e)
+    }))
+}
+
+// What `open_database` and `open_database_at` return: the bytes to load plus
+// what the UI needs to know about the file they came from
+#[derive(Serialize)]
+struct OpenResponse {
+    data: Vec<u8>,
+    path: Option<String>,
+    path_display: String,
+    size: u64,
+    was_compressed: bool,
+    was_encrypted: bool,
+    // The file doesn't match its `.sha256` sidecar; false when there is none
+    checksum_mismatch: bool,
+}
+
+// Refuse to adopt a file another running brewcode has open
+fn ensure_unlocked(path: &std::path::Path) -> Result<(), BrewError> {
+    match lock::held_elsewhere(path) {
+        Some(holder) => Err(BrewError::LockedByOther {
+            pid: holder.pid,
+            since: holder.since,
+        }),
+        None => Ok(()),
+    }
+}
+
+// Move our lock from the current document to `path`. A lock file that can't
+// be written (read-only folder, say) only loses the warning for other
+// instances, so it isn't fatal.
+fn swap_lock(state: &AppState, path: &std::path::Path) {
+    if let Some(previous) = state.current_db_path.lock().unwrap().as_deref() {
+        if previous != path {
+            lock::release(previous);
+        }
+    }
+    if let Err(e) = lock::acquire(path, unix_millis(std::time::SystemTime::now())) {
+        log::warn!("Failed to write lock file for {}: {}", path.display(), e);
+    }
+}
+
+// Fail early if `len` bytes won't fit on the volume holding `path`, rather
+// than running out partway through the write
+fn ensure_free_space(path: &std::path::Path, len: usize) -> Result<(), BrewError> {
+    let required = len as u64;
+    match storage::available_space(path) {
+        Some(available) if available < required => {
+            Err(BrewError::InsufficientSpace { required, available })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Record a save, open or export in the log: info on success, error on
+// failure. Only the path and byte count are logged, never file contents or
+// passphrases.
+fn log_operation<T>(
+    state: &AppState,
+    operation: &str,
+    path: &std::path::Path,
+    bytes: usize,
+    result: &Result<T, BrewError>,
+) {
+    match result {
+        Ok(_) => log::info!("{} {}: {} bytes", operation, path.display(), bytes),
+        Err(BrewError::Cancelled) => log::info!("{} {} cancelled", operation, path.display()),
+        Err(e) => log::error!("{} {} failed: {}", operation, path.display(), e),
+    }
+    record_activity(state, operation, path, result);
+}
+
+// Add an entry to this session's activity log, dropping the oldest past the
+// cap. A failure also becomes the last error, until the same operation next
+// succeeds.
+fn record_activity<T>(
+    state: &AppState,
+    operation: &str,
+    path: &std::path::Path,
+    result: &Result<T, BrewError>,
+) {
+    let at = unix_millis(std::time::SystemTime::now());
+    let path = path.to_string_lossy().to_string();
+    let (outcome, message) = match result {
+        Ok(_) => ("ok", None),
+        Err(BrewError::Cancelled) => ("cancelled", None),
+        Err(e) => (e.kind(), Some(e.to_string())),
+    };
+    {
+        let mut last_error = state.last_error.lock().unwrap();
+        if let Some(message) = &message {
+            *last_error = Some(ErrorDetails {
+                at,
+                operation: operation.to_string(),
+                path: path.clone(),
+                kind: outcome,
+                message: message.clone(),
+            });
+        } else if result.is_ok() && last_error.as_ref().is_some_and(|e| e.operation == operation) {
+            *last_error = None;
+        }
+    }
+    let mut activity = state.activity.lock().unwrap();
+    activity.push_back(ActivityEntry {
+        at,
+        kind: operation.to_string(),
+        path,
+        outcome,
+        message,
+    });
+    while activity.len() > MAX_ACTIVITY_ENTRIES {
+        activity.pop_front();
+    }
+}
+
+// Rotate `backups` backups and atomically write `data` to `path` on a
+// blocking thread, so a hung network drive can't stall the runtime. Fails
+// with `Timeout` after the configured limit; the abandoned write then
+// deletes its temp file instead of landing late.
+async fn write_bounded(
+    state: &AppState,
+    path: &std::path::Path,
+    data: Vec<u8>,
+    backups: u8,
+    cancelled: Arc<AtomicBool>,
+    progress: impl FnMut(usize) + Send + 'static,
+) -> Result<(), BrewError> {
+    let settings = state.settings.lock().unwrap().clone();
+    let seconds = settings.save_timeout_secs;
+
+    let task = {
+        let path = path.to_path_buf();
+        let cancelled = cancelled.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            storage::rotate_backups(&path, backups)?;
+            storage::atomic_write_cancellable(
+                &path,
+                &data,
+                settings.temp_strategy,
+                settings.durability,
+                &cancelled,
+                progress,
+            )
+        })
+    };
+
+    match tokio::time::timeout(std::time::Duration::from_secs(seconds), task).await {
+        Ok(Ok(Err(_))) if cancelled.load(Ordering::SeqCst) => Err(BrewError::Cancelled),
+        Ok(Ok(result)) => Ok(result?),
+        Ok(Err(e)) => Err(BrewError::Io(std::io::Error::other(e.to_string()))),
+        Err(_) => {
+            cancelled.store(true, Ordering::SeqCst);
+            Err(BrewError::Timeout { seconds })
+        }
+    }
+}
+
+// `write_bounded` for saves, announced to the frontend as `save-started`,
+// `save-progress` (large files only) and `save-finished` or `save-failed`
+async fn write_with_events<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AppState,
+    path: &std::path::Path,
+    data: Vec<u8>,
+    backups: u8,
+) -> Result<(), BrewError> {
+    let path_str = path_string(path);
+    let total_bytes = data.len() as u64;
+    let started = SaveProgress {
+        path: path_str.clone(),
+        bytes_written: 0,
+        total_bytes,
+    };
+    emit_logged(app, "save-started", started);
+
+    let progress = {
+        let app = app.clone();
+        let path = path_str.clone();
+        move |bytes_written: usize| {
+            if total_bytes >= SAVE_PROGRESS_MIN_BYTES {
+                let progress = SaveProgress {
+                    path: path.clone(),
+                    bytes_written: bytes_written as u64,
+                    total_bytes,
+                };
+                emit_logged(&app, "save-progress", progress);
+            }
+        }
+    };
+
+    // A sidecar left from when the setting was on is kept current too, so it
+    // never reports a mismatch for our own save
+    let sidecar = state.settings.lock().unwrap().write_checksum_sidecar
+        || storage::checksum_sidecar_path(path).exists();
+    let digest = sidecar.then(|| storage::sha256_hex(&data));
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let result = write_bounded(state, path, data, backups, cancelled, progress).await;
+    match &result {
+        Ok(()) => {
+            if let Some(digest) = digest {
+                if let Err(e) = storage::write_checksum_sidecar(path, &digest, total_bytes) {
+                    log::warn!("Failed to write checksum sidecar {}: {}", path.display(), e);
+                }
+            }
+            let finished = SaveProgress {
+                path: path_str,
+                bytes_written: total_bytes,
+                total_bytes,
+            };
+            emit_logged(app, "save-finished", finished);
+        }
+        Err(e) => {
+            let failure = SaveFailure {
+                path: path_str,
+                kind: e.kind(),
+                message: e.to_string(),
+            };
+            emit_logged(app, "save-failed", failure);
+        }
+    }
+    result
+}
+
+// Atomically write `data` to `path` and make it the current document
+async fn write_and_adopt(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    path: &std::path::Path,
+    data: Vec<u8>,
+) -> Result<SaveResponse, BrewError> {
+    let len = data.len();
+    let checksum = storage::sha256_hex(&data);
+    // Checked before writing, which always leaves a file there
+    let created_new = !path.exists();
+
+    let checked = ensure_safe_location(state, path)
+        .and_then(|_| ensure_unlocked(path))
+        .and_then(|_| ensure_free_space(path, len))
+        .and_then(|_| {
+            if is_current_file(state, path) {
+                ensure_no_live_connection(state)
+            } else {
+                Ok(())
+            }
+        });
+    let written = match checked {
+        Ok(()) => write_with_events(app, state, path, data, 0).await,
+        Err(e) => Err(e),
+    };
+    log_operation(state, "save", path, len, &written);
+    written?;
+    let mirror = write_mirror(state, path);
+    // The live connection, if any, is to the file we're leaving
+    *state.live_connection.lock().unwrap() = None;
+    swap_lock(state, path);
+    record_disk_stamp(state, path);
+    *state.read_only.lock().unwrap() = false;
+    *state.encrypted.lock().unwrap() = false;
+    *state.saved_checksum.lock().unwrap() = Some(checksum);
+    clear_journal(app);
+
+    set_current_path(app, state, Some(path.to_path_buf()));
+    set_dirty(app, state, false);
+    remember_recent(app, state, path);
+    remember_directory(app, state, path);
+
+    Ok(SaveResponse {
+        created_new,
+        ..SaveResponse::saved(path, Some(len as u64))
+    }
+    .mirrored(mirror))
+}
+
+// Give extensionless save paths the configured extension (`.db` by default),
+// and flag any other extension so the user knows OS file associations won't
+// pick it up
+fn normalize_db_path(
+    path: PathBuf,
+    config: &DialogConfig,
+    enforce_extension: Option<bool>,
+) -> (PathBuf, Option<String>) {
+    let Some(default_ext) = config.extensions.first() else {
+        return (path, None);
+    };
+    if !enforce_extension.unwrap_or(true) {
+        return (path, None);
+    }
+
+    match path.extension() {
+        None => (path.with_extension(default_ext), None),
+        Some(ext) if config.extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)) => (path, None),
+        Some(ext) => {
+            let warning = format!(
+                "Saved with a .{} extension; other apps may not recognise it as a database",
+                ext.to_string_lossy()
+            );
+            (path, Some(warning))
+        }
+    }
+}
+
+// Save database to a new location (opens save dialog)
+#[tauri::command]
+async fn save_database_as(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+    enforce_extension: Option<bool>,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    validate_save_data(&data, skip_validation)?;
+    let config = state.dialog_config.lock().unwrap().clone();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(&config.default_file_name)
+        .blocking_save_file();
+
+    let path = dialog_path(file_path)?;
+    let (path, warning) = normalize_db_path(path, &config, enforce_extension);
+    let response = write_and_adopt(&app, &state, &path, data).await?;
+    Ok(SaveResponse {
+        warning: warning.or(response.warning),
+        ..response
+    })
+}
+
+// Save database to an explicit path without a dialog; it becomes the current path
+#[tauri::command]
+async fn save_database_to(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    data: Vec<u8>,
+    enforce_extension: Option<bool>,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    validate_save_data(&data, skip_validation)?;
+    let config = state.dialog_config.lock().unwrap().clone();
+    let path = storage::path_from_key(&path);
+    let (path, warning) = normalize_db_path(path, &config, enforce_extension);
+    let response = write_and_adopt(&app, &state, &path, data).await?;
+    Ok(SaveResponse {
+        warning: warning.or(response.warning),
+        ..response
+    })
+}
+
+// Save database to the current location
+#[tauri::command]
+async fn save_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+    force: bool,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    validate_save_data(&data, skip_validation)?;
+    // Writing plaintext over an encrypted file would silently decrypt it
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+    if !force {
+        ensure_same_file(&state, &path)?;
+    }
+    let checksum = storage::sha256_hex(&data);
+    overwrite_current(&app, &state, &path, data, checksum).await
+}
+
+// What `save_database_resolving` does when the file changed on disk
+#[derive(Clone, Copy, Deserialize)]
+enum ConflictResolution {
+    Overwrite,
+    // Save to a new file picked in the save dialog, which becomes current
+    SaveAsCopy,
+    Abort,
+}
+
+// Save to the current location, handling a file changed by another program
+// according to `strategy` instead of writing over it blindly
+#[tauri::command]
+async fn save_database_resolving(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+    strategy: ConflictResolution,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    validate_save_data(&data, skip_validation)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+
+    if changed_externally(&state, &path)? {
+        match strategy {
+            ConflictResolution::Overwrite => {}
+            ConflictResolution::SaveAsCopy => {
+                let config = state.dialog_config.lock().unwrap().clone();
+                let file_path = file_dialog(&app, &state, &config)
+                    .set_file_name(&config.default_file_name)
+                    .blocking_save_file();
+                let target = dialog_path(file_path)?;
+                let (target, warning) = normalize_db_path(target, &config, None);
+                let response = write_and_adopt(&app, &state, &target, data).await?;
+                return Ok(SaveResponse {
+                    warning: warning.or(response.warning),
+                    ..response
+                });
+            }
+            ConflictResolution::Abort => {
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(unix_millis);
+                return Err(BrewError::ExternalChangeDetected { modified });
+            }
+        }
+    }
+    let checksum = storage::sha256_hex(&data);
+    overwrite_current(&app, &state, &path, data, checksum).await
+}
+
+// Replace the current file with `bytes`, rotating backups first. `checksum`
+// is of the bytes the frontend holds, used for dirty tracking; it differs
+// from `bytes` only for encrypted saves.
+async fn overwrite_current<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AppState,
+    path: &std::path::Path,
+    bytes: Vec<u8>,
+    checksum: String,
+) -> Result<SaveResponse, BrewError> {
+    ensure_writable(state)?;
+    ensure_no_live_connection(state)?;
+    let backup_count = state.settings.lock().unwrap().backup_count;
+    let len = bytes.len();
+
+    let written = match ensure_free_space(path, len) {
+        Ok(()) => write_with_events(app, state, path, bytes, backup_count).await,
+        Err(e) => Err(e),
+    };
+    log_operation(state, "save", path, len, &written);
+    written?;
+    let mirror = write_mirror(state, path);
+    record_disk_stamp(state, path);
+    *state.saved_checksum.lock().unwrap() = Some(checksum);
+    set_dirty(app, state, false);
+    clear_journal(app);
+    remember_recent(app, state, path);
+
+    Ok(SaveResponse::saved(path, Some(len as u64)).mirrored(mirror))
+}
+
+// Encrypt the database with `passphrase` and save it. An encrypted document
+// is written back to its own path; anything else goes through the save
+// dialog and the encrypted file becomes the current document.
+#[tauri::command]
+async fn save_database_encrypted(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+    passphrase: String,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    validate_save_data(&data, skip_validation)?;
+    let ciphertext = crypto::encrypt(&data, &passphrase)?;
+    let checksum = storage::sha256_hex(&data);
+
+    if *state.encrypted.lock().unwrap() {
+        let current = state.current_db_path.lock().unwrap().clone();
+        if let Some(path) = current {
+            ensure_same_file(&state, &path)?;
+            return overwrite_current(&app, &state, &path, ciphertext, checksum).await;
+        }
+    }
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(&config.default_file_name)
+        .blocking_save_file();
+
+    let path = dialog_path(file_path)?;
+    let (path, warning) = normalize_db_path(path, &config, None);
+    let response = write_and_adopt(&app, &state, &path, ciphertext).await?;
+    *state.encrypted.lock().unwrap() = true;
+    *state.saved_checksum.lock().unwrap() = Some(checksum);
+    Ok(SaveResponse {
+        warning: warning.or(response.warning),
+        ..response
+    })
+}
+
+// Make sure bytes from the frontend are a database SQLite can read before
+// they replace anything on disk, so a frontend bug can't destroy the file
+fn validate_save_data(data: &[u8], skip: Option<bool>) -> Result<(), BrewError> {
+    if skip.unwrap_or(false) {
+        return Ok(());
+    }
+    if !storage::has_sqlite_header(data) {
+        return Err(BrewError::InvalidSaveData("missing SQLite header".to_string()));
+    }
+    sqlite::open_bytes(data)
+        .and_then(|conn| sqlite::user_tables(&conn))
+        .map_err(|e| BrewError::InvalidSaveData(e.to_string()))?;
+    Ok(())
+}
+
+// Refuse to change a current document that's read-only on disk, whether it
+// was when we opened it or was made read-only since
+fn ensure_writable(state: &AppState) -> Result<(), BrewError> {
+    if *state.read_only.lock().unwrap() {
+        return Err(BrewError::ReadOnly);
+    }
+    let current = state.current_db_path.lock().unwrap().clone();
+    match current.as_deref().map(storage::is_read_only) {
+        Some(Ok(true)) => Err(BrewError::ReadOnly),
+        Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Reject files that aren't SQLite databases unless the caller forces it
+fn validate_database(data: &[u8], force: bool) -> Result<(), BrewError> {
+    if force || storage::has_sqlite_header(data) {
+        Ok(())
+    } else {
+        Err(BrewError::InvalidDatabase(
+            "the file does not start with a SQLite header".to_string(),
+        ))
+    }
+}
+
+// Most bytes one open may bring into memory, before or after decompressing:
+// the `max_open_bytes` setting, or no limit with `allow_large`
+fn open_limit(state: &AppState, allow_large: bool) -> u64 {
+    if allow_large {
+        return u64::MAX;
+    }
+    state.settings.lock().unwrap().max_open_bytes
+}
+
+// Refuse to read a file over the open limit into memory, so picking the
+// wrong huge file can't exhaust it
+fn ensure_open_size(
+    state: &AppState,
+    path: &std::path::Path,
+    allow_large: bool,
+) -> Result<(), BrewError> {
+    let limit = open_limit(state, allow_large);
+    let size = std::fs::metadata(path)?.len();
+    if size > limit {
+        return Err(BrewError::FileTooLarge { size, limit });
+    }
+    Ok(())
+}
+
+// Read all of `path` after checking it against the open limit
+fn read_bounded(
+    state: &AppState,
+    path: &std::path::Path,
+    allow_large: bool,
+) -> Result<Vec<u8>, BrewError> {
+    ensure_open_size(state, path, allow_large)?;
+    Ok(std::fs::read(path)?)
+}
+
+// Read a database from disk and make it the current document
+fn load_database(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    path: &std::path::Path,
+    force: bool,
+    allow_large: bool,
+) -> Result<OpenResponse, BrewError> {
+    let result = read_bounded(state, path, allow_large).and_then(|raw| {
+        let size = raw.len() as u64;
+        let was_compressed = storage::is_gzip(&raw);
+        let checksum_mismatch = storage::read_checksum_sidecar(path)
+            .is_some_and(|(digest, len)| len != size || digest != storage::sha256_hex(&raw));
+        let data = adopt_loaded(app, state, path, raw, force, allow_large)?;
+        Ok(OpenResponse {
+            data,
+            path: Some(path_string(path)),
+            path_display: path_display(path),
+            size,
+            was_compressed,
+            // Encrypted files are opened with `open_database_encrypted`
+            was_encrypted: false,
+            checksum_mismatch,
+        })
+    });
+    log_operation(state, "open", path, result.as_ref().map_or(0, |r| r.data.len()), &result);
+    result
+}
+
+// Turn bytes read from disk into a database the frontend can load:
+// decompress gzip to at most `limit` bytes, then check the header and schema
+// version
+fn decode_database(mut data: Vec<u8>, force: bool, limit: u64) -> Result<Vec<u8>, BrewError> {
+    if crypto::is_encrypted(&data) {
+        return Err(BrewError::PassphraseRequired);
+    }
+    if storage::is_gzip(&data) {
+        data = storage::gunzip(&data, limit).map_err(|e| {
+            BrewError::InvalidDatabase(format!("failed to decompress gzip file: {}", e))
+        })?;
+    }
+    validate_database(&data, force)?;
+    // Writing to a file from a newer brewcode could corrupt it
+    if storage::has_sqlite_header(&data) {
+        migrations::ensure_supported(migrations::header_version(&data).unwrap_or(0))?;
+    }
+    Ok(data)
+}
+
+// Validate bytes read from `path` and make them the current document
+fn adopt_loaded(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    path: &std::path::Path,
+    data: Vec<u8>,
+    force: bool,
+    allow_large: bool,
+) -> Result<Vec<u8>, BrewError> {
+    let data = decode_database(data, force, open_limit(state, allow_large))?;
+    ensure_unlocked(path)?;
+    swap_lock(state, path);
+
+    // The previous document's connection and history don't apply to this one
+    *state.live_connection.lock().unwrap() = None;
+    state.undo_stack.lock().unwrap().clear();
+    state.redo_stack.lock().unwrap().clear();
+    *state.encrypted.lock().unwrap() = false;
+
+    record_disk_stamp(state, path);
+    *state.read_only.lock().unwrap() = storage::is_read_only(path)?;
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_hex(&data));
+
+    set_current_path(app, state, Some(path.to_path_buf()));
+    set_dirty(app, state, false);
+    remember_recent(app, state, path);
+    remember_directory(app, state, path);
+
+    Ok(data)
+}
+
+// Path picked in a file dialog. Mobile platforms may answer with a URL: a
+// `file://` one is used as its path, but others such as Android `content://`
+// can't take atomic writes, backups or locks, so they're refused rather than
+// passed off as a cancel.
+fn dialog_path(file_path: Option<tauri_plugin_dialog::FilePath>) -> Result<PathBuf, BrewError> {
+    use tauri_plugin_dialog::FilePath;
+
+    match file_path {
+        None => Err(BrewError::Cancelled),
+        Some(FilePath::Path(path)) => Ok(path),
+        Some(FilePath::Url(url)) => url
+            .to_file_path()
+            .map_err(|_| BrewError::UnsupportedLocation(url.to_string())),
+    }
+}
+
+// Show the open dialog for database files (plain or gzipped)
+fn pick_database_file(app: &tauri::AppHandle, state: &AppState) -> Result<PathBuf, BrewError> {
+    let config = state.dialog_config.lock().unwrap().clone();
+    // The first filter stays the one selected by default
+    let mut dialog = file_dialog(app, state, &config)
+        .add_filter(format!("Compressed {}", config.filter_label), &["gz"]);
+    if config.add_all_files_filter {
+        dialog = dialog.add_filter("All Files (*.*)", &["*"]);
+    }
+    let file_path = dialog.blocking_pick_file();
+
+    dialog_path(file_path)
+}
+
+// Open existing database file. Files over the `max_open_bytes` setting fail
+// with `FileTooLarge` unless `allow_large` is set; likewise for the other
+// open commands.
+#[tauri::command]
+async fn open_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    force: bool,
+    allow_large: Option<bool>,
+) -> Result<OpenResponse, BrewError> {
+    let path = pick_database_file(&app, &state)?;
+    load_database(&app, &state, &path, force, allow_large.unwrap_or(false))
+}
+
+// Open a database at a known path without a dialog (recent files, drag-and-drop)
+#[tauri::command]
+async fn open_database_at(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    allow_large: Option<bool>,
+) -> Result<OpenResponse, BrewError> {
+    let allow_large = allow_large.unwrap_or(false);
+    load_database(&app, &state, &storage::path_from_key(&path), false, allow_large)
+}
+
+// Open a database in 4 MB chunks, emitting `open-progress` after each one.
+// Opens a dialog unless `path` is given; `cancel_open` aborts the read.
+#[tauri::command]
+async fn open_database_streamed(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: Option<String>,
+    force: bool,
+    allow_large: Option<bool>,
+) -> Result<Vec<u8>, BrewError> {
+    let path = match path {
+        Some(path) => storage::path_from_key(&path),
+        None => pick_database_file(&app, &state)?,
+    };
+
+    let allow_large = allow_large.unwrap_or(false);
+    let result = ensure_open_size(&state, &path, allow_large)
+        .and_then(|_| read_chunked(&app, &state, &path))
+        .and_then(|data| adopt_loaded(&app, &state, &path, data, force, allow_large));
+    log_operation(&state, "open", &path, result.as_ref().map_or(0, Vec::len), &result);
+    result
+}
+
+// Read `path` into memory for `open_database_streamed`
+fn read_chunked(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    path: &std::path::Path,
+) -> Result<Vec<u8>, BrewError> {
+    use std::io::Read;
+
+    state.open_cancelled.store(false, Ordering::SeqCst);
+    let mut file = std::fs::File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+    let mut data = Vec::with_capacity(total_bytes as usize);
+    let mut chunk = vec![0u8; OPEN_CHUNK_BYTES];
+
+    loop {
+        if state.open_cancelled.load(Ordering::SeqCst) {
+            return Err(BrewError::Cancelled);
+        }
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..read]);
+
+        let progress = OpenProgress {
+            bytes_read: data.len() as u64,
+            total_bytes,
+        };
+        if let Err(e) = app.emit("open-progress", progress) {
+            log::warn!("Failed to emit open-progress: {}", e);
+        }
+    }
+
+    Ok(data)
+}
+
+// Open a file written by `save_database_encrypted` and return the decrypted
+// bytes. A wrong passphrase fails with `WrongPassphrase`.
+#[tauri::command]
+async fn open_database_encrypted(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    passphrase: String,
+    allow_large: Option<bool>,
+) -> Result<Vec<u8>, BrewError> {
+    let path = pick_database_file(&app, &state)?;
+    let allow_large = allow_large.unwrap_or(false);
+    let result = read_bounded(&state, &path, allow_large)
+        .and_then(|data| match crypto::decrypt(&data, &passphrase) {
+            // Tell the frontend which file was rejected so it can ask for its hint
+            Err(crypto::CryptoError::WrongPassphrase) => Err(BrewError::WrongPassphrase {
+                path: Some(path_string(&path)),
+            }),
+            other => Ok(other?),
+        })
+        .and_then(|plaintext| adopt_loaded(&app, &state, &path, plaintext, false, allow_large));
+    log_operation(&state, "open encrypted", &path, result.as_ref().map_or(0, Vec::len), &result);
+    let data = result?;
+    *state.encrypted.lock().unwrap() = true;
+    Ok(data)
+}
+
+// Download a database over http(s) and return its bytes without making it
+// the current document: a remote file has no local path to save back to, so
+// `path` is None and the URL is only the `path_display`. The body is streamed
+// to a temp file, removed afterwards. Emits `open-progress` like
+// `open_database_streamed` and honours `cancel_open`.
+#[tauri::command]
+async fn open_database_from_url(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+) -> Result<OpenResponse, BrewError> {
+    let parsed = reqwest::Url::parse(&url).map_err(|e| BrewError::InvalidUrl(e.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(BrewError::InvalidUrl("only http and https are supported".to_string()));
+    }
+
+    state.open_cancelled.store(false, Ordering::SeqCst);
+    let client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(DOWNLOAD_CONNECT_TIMEOUT_SECS))
+        .read_timeout(std::time::Duration::from_secs(DOWNLOAD_READ_TIMEOUT_SECS))
+        .build()
+        .map_err(download_error)?;
+    let response = client
+        .get(parsed)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(download_error)?;
+    if response.content_length().unwrap_or(0) > MAX_DOWNLOAD_BYTES {
+        return Err(BrewError::DownloadTooLarge { limit: MAX_DOWNLOAD_BYTES });
+    }
+
+    let (temp, mut file) = storage::create_temp_file("download")?;
+    let downloaded = download_into(&app, &state, response, &mut file).await;
+    drop(file);
+    let raw = downloaded.and_then(|_| Ok(std::fs::read(&temp)?));
+    if let Err(e) = std::fs::remove_file(&temp) {
+        log::warn!("Failed to remove download {}: {}", temp.display(), e);
+    }
+    let raw = raw?;
+
+    let size = raw.len() as u64;
+    let was_compressed = storage::is_gzip(&raw);
+    let result = decode_database(raw, false, MAX_DOWNLOAD_BYTES);
+    let len = result.as_ref().map_or(0, Vec::len);
+    log_operation(&state, "download", std::path::Path::new(&url), len, &result);
+    Ok(OpenResponse {
+        data: result?,
+        path: None,
+        path_display: url,
+        size,
+        was_compressed,
+        was_encrypted: false,
+        checksum_mismatch: false,
+    })
+}
+
+fn download_error(e: reqwest::Error) -> BrewError {
+    if e.is_timeout() {
+        return BrewError::Network("the server stopped responding".to_string());
+    }
+    BrewError::Network(e.to_string())
+}
+
+// Write the body of `response` to `file`, emitting `open-progress` after each
+// chunk. Counts what actually arrives against `MAX_DOWNLOAD_BYTES`, since
+// Content-Length can be missing or wrong.
+async fn download_into(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    mut response: reqwest::Response,
+    file: &mut std::fs::File,
+) -> Result<(), BrewError> {
+    use std::io::Write;
+
+    let total_bytes = response.content_length().unwrap_or(0);
+    let mut bytes_read = 0u64;
+    while let Some(chunk) = response.chunk().await.map_err(download_error)? {
+        if state.open_cancelled.load(Ordering::SeqCst) {
+            return Err(BrewError::Cancelled);
+        }
+        bytes_read += chunk.len() as u64;
+        if bytes_read > MAX_DOWNLOAD_BYTES {
+            return Err(BrewError::DownloadTooLarge { limit: MAX_DOWNLOAD_BYTES });
+        }
+        file.write_all(&chunk)?;
+        let progress = OpenProgress {
+            bytes_read,
+            total_bytes,
+        };
+        emit_logged(app, "open-progress", progress);
+    }
+    Ok(())
+}
+
+// Pick a database and return its bytes without making it the current
+// document: the path, recent files, lock and dirty state are left alone.
+// Useful for peeking at a backup.
+#[tauri::command]
+async fn inspect_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, BrewError> {
+    let path = pick_database_file(&app, &state)?;
+    let result = read_bounded(&state, &path, false)
+        .and_then(|data| decode_database(data, false, open_limit(&state, false)));
+    log_operation(&state, "inspect", &path, result.as_ref().map_or(0, Vec::len), &result);
+    let data = result?;
+    remember_directory(&app, &state, &path);
+    Ok(data)
+}
+
+// Compare the current file on disk with the frontend's unsaved bytes, table
+// by table, so the user can see what an overwrite would change
+#[tauri::command]
+async fn diff_against_disk(
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+) -> Result<diff::DbDiff, BrewError> {
+    let path = current_path(&state)?;
+    let limit = open_limit(&state, false);
+    let disk = decode_database(read_bounded(&state, &path, false)?, false, limit)?;
+    diff::diff(&disk, &data)
+}
+
+// Compare two database files on disk, independent of the current document.
+// Neither file is modified; encrypted files are refused.
+#[tauri::command]
+async fn compare_files(
+    state: State<'_, AppState>,
+    a: String,
+    b: String,
+) -> Result<diff::DbDiff, BrewError> {
+    let limit = open_limit(&state, false);
+    let (a, b) = (storage::path_from_key(&a), storage::path_from_key(&b));
+    let first = decode_database(read_bounded(&state, &a, false)?, false, limit)?;
+    let second = decode_database(read_bounded(&state, &b, false)?, false, limit)?;
+    diff::compare(&first, &second)
+}
+
+// Abort an in-flight `open_database_streamed`
+#[tauri::command]
+async fn cancel_open(state: State<'_, AppState>) -> Result<(), BrewError> {
+    state.open_cancelled.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+// Progress callback for the table-by-table exports, emitting `export-progress`
+fn export_progress(app: &tauri::AppHandle) -> impl FnMut(usize, usize) {
+    let app = app.clone();
+    move |processed, total| {
+        let progress = ExportProgress {
+            processed: processed as u64,
+            total: total as u64,
+        };
+        emit_logged(&app, "export-progress", progress);
+    }
+}
+
+// Abort the export in progress. Nothing is left at the target: a file being
+// written is only a temp file until the export completes.
+#[tauri::command]
+async fn cancel_export(state: State<'_, AppState>) -> Result<(), BrewError> {
+    state.export_cancelled.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+// Write an export that doesn't become the current document, refusing the
+// current file as a target. `cancel_export` abandons the write and removes
+// the partial file.
+async fn write_export(
+    state: &AppState,
+    path: &std::path::Path,
+    bytes: Vec<u8>,
+) -> Result<(), BrewError> {
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    let len = bytes.len();
+    let checked = ensure_not_current(state, path)
+        .and_then(|_| ensure_safe_location(state, path))
+        .and_then(|_| ensure_free_space(path, len));
+    let cancelled = state.export_cancelled.clone();
+    let written = match checked {
+        Ok(()) => write_bounded(state, path, bytes, 0, cancelled, |_| {}).await,
+        Err(e) => Err(e),
+    };
+    log_operation(state, "export", path, len, &written);
+    written
+}
+
+// Export database copy to a different location (doesn't change current path).
+// With `compress` the bytes are gzipped; `open_database` reads those back.
+#[tauri::command]
+async fn export_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+    compress: bool,
+) -> Result<SaveResponse, BrewError> {
+    let config = state.dialog_config.lock().unwrap().clone();
+    let dialog = if compress {
+        base_dialog(&app, &state)
+            .add_filter(format!("Compressed {}", config.filter_label), &["gz"])
+            .set_file_name(format!("{}.gz", config.default_file_name))
+    } else {
+        file_dialog(&app, &state, &config).set_file_name(&config.default_file_name)
+    };
+    let file_path = dialog.blocking_save_file();
+
+    let path = dialog_path(file_path)?;
+    let bytes = if compress { storage::gzip(&data)? } else { data };
+    let len = bytes.len();
+    write_export(&state, &path, bytes).await?;
+    remember_directory(&app, &state, &path);
+
+    Ok(SaveResponse::saved(&path, Some(len as u64)))
+}
+
+// Package the current database and a metadata file into one encrypted
+// `.brewbundle` for sharing, chosen with a save dialog
+#[tauri::command]
+async fn export_secure_bundle(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    passphrase: String,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let limit = open_limit(&state, false);
+    let database = decode_database(read_bounded(&state, &path, false)?, false, limit)?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let now = unix_millis(std::time::SystemTime::now());
+    let sealed = bundle::seal(&database, file_name, now, &passphrase)?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let bundle_name = std::path::Path::new(&config.default_file_name).with_extension("brewbundle");
+    let file_path = base_dialog(&app, &state)
+        .add_filter("brewcode Secure Bundle", &["brewbundle"])
+        .set_file_name(bundle_name.to_string_lossy())
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    let len = sealed.len();
+    write_export(&state, &target, sealed).await?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SaveResponse::saved(&target, Some(len as u64)))
+}
+
+// Decrypt a secure bundle and return its database and metadata. Like
+// `inspect_database`, the result doesn't become the current document.
+#[tauri::command]
+async fn open_secure_bundle(
+    state: State<'_, AppState>,
+    path: String,
+    passphrase: String,
+) -> Result<bundle::OpenedBundle, BrewError> {
+    let path = storage::path_from_key(&path);
+    let result = read_bounded(&state, &path, false)
+        .and_then(|data| bundle::open(&data, &passphrase))
+        .map_err(|e| match e {
+            BrewError::WrongPassphrase { .. } => BrewError::WrongPassphrase {
+                path: Some(path_string(&path)),
+            },
+            e => e,
+        });
+    let len = result.as_ref().map_or(0, |opened| opened.data.len());
+    log_operation(&state, "open bundle", &path, len, &result);
+    result
+}
+
+// Package `data` with the current settings and view state into a portable
+// `.brewproj`, chosen with a save dialog. Like an export, the project doesn't
+// become the current document.
+#[tauri::command]
+async fn save_project(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Vec<u8>,
+) -> Result<SaveResponse, BrewError> {
+    validate_save_data(&data, None)?;
+    let settings = state.settings.lock().unwrap().clone();
+    let view_state = state
+        .current_db_path
+        .lock()
+        .unwrap()
+        .as_deref()
+        .and_then(|path| state.view_states.lock().unwrap().get(&path_string(path)).cloned());
+    let now = unix_millis(std::time::SystemTime::now());
+    let packed = project::pack(&data, &settings, view_state.as_ref(), now)?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let project_name = std::path::Path::new(&config.default_file_name).with_extension("brewproj");
+    let file_path = base_dialog(&app, &state)
+        .add_filter("brewcode Project", &["brewproj"])
+        .set_file_name(project_name.to_string_lossy())
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    let len = packed.len();
+    write_export(&state, &target, packed).await?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SaveResponse::saved(&target, Some(len as u64)))
+}
+
+// Database, settings and view state unpacked from a `.brewproj`
+#[derive(Serialize)]
+struct OpenedProject {
+    data: Vec<u8>,
+    // The user's settings after the project's were applied
+    settings: settings::Settings,
+    view_state: Option<ViewState>,
+    manifest: project::ProjectManifest,
+}
+
+// Unpack a `.brewproj` and apply the few settings it may carry (see
+// `project::ProjectSettings`). The database bytes and view state are returned
+// for the frontend to load; like `open_secure_bundle`, the project doesn't
+// become the current document.
+#[tauri::command]
+async fn open_project(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<OpenedProject, BrewError> {
+    let limit = open_limit(&state, false);
+    let path = storage::path_from_key(&path);
+    let result = read_bounded(&state, &path, false)
+        .and_then(|archive| project::unpack::<ViewState>(&archive))
+        .and_then(|mut project| {
+            project.data = decode_database(std::mem::take(&mut project.data), false, limit)?;
+            Ok(project)
+        });
+    let len = result.as_ref().map_or(0, |project| project.data.len());
+    log_operation(&state, "open project", &path, len, &result);
+    let project = result?;
+
+    Ok(OpenedProject {
+        data: project.data,
+        settings: update_settings_with(&app, &state, project.settings.to_partial()),
+        view_state: project.view_state,
+        manifest: project.manifest,
+    })
+}
+
+// Save a copy of the current database converted to schema `target_version`,
+// e.g. for a colleague on an older brewcode. The current file is untouched:
+// `write_export` refuses it as the target.
+#[tauri::command]
+async fn export_for_version(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    target_version: u32,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let limit = open_limit(&state, false);
+    let data = decode_database(read_bounded(&state, &path, false)?, false, limit)?;
+    let mut conn = sqlite::open_bytes_mut(&data)?;
+    let report = migrations::convert(&mut conn, target_version)?;
+    let bytes = conn.serialize(rusqlite::MAIN_DB)?.to_vec();
+    drop(conn);
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let stem = std::path::Path::new(&config.default_file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(format!("{}-v{}", stem, report.to))
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    let (target, warning) = normalize_db_path(target, &config, None);
+    let len = bytes.len();
+    write_export(&state, &target, bytes).await?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SaveResponse {
+        warning,
+        ..SaveResponse::saved(&target, Some(len as u64))
+    })
+}
+
+// Save a frozen copy of the current file for sharing, stamped with a
+// `_brewcode_snapshot` table naming its source, time and checksum so
+// `get_db_info` can tell it apart. The copy is made read-only on disk; the
+// current file is not touched, and `write_export` refuses it as the target.
+#[tauri::command]
+async fn export_readonly_snapshot(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let limit = open_limit(&state, false);
+    let source = decode_database(read_bounded(&state, &path, false)?, false, limit)?;
+    let info = export::SnapshotInfo {
+        source_path: path_display(&path),
+        created_at: unix_millis(std::time::SystemTime::now()),
+        sha256: storage::sha256_hex(&source),
+    };
+    let data = export::snapshot(&source, &info)?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(format!("{}-snapshot", stem))
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    let (target, warning) = normalize_db_path(target, &config, None);
+    let len = data.len();
+    write_export(&state, &target, data).await?;
+    remember_directory(&app, &state, &target);
+    let read_only = std::fs::metadata(&target).and_then(|metadata| {
+        let mut permissions = metadata.permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&target, permissions)
+    });
+    if let Err(e) = read_only {
+        log::warn!("Failed to mark {} read-only: {}", target.display(), e);
+    }
+
+    Ok(SaveResponse {
+        warning,
+        ..SaveResponse::saved(&target, Some(len as u64))
+    })
+}
+
+// Save a new database containing only `tables` from the current file
+#[tauri::command]
+async fn export_subset(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    tables: Vec<String>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    let data = export::subset(&path, &tables, &state.export_cancelled, export_progress(&app))?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(&config.default_file_name)
+        .blocking_save_file();
+
+    let path = dialog_path(file_path)?;
+    let (path, warning) = normalize_db_path(path, &config, None);
+    let len = data.len();
+    write_export(&state, &path, data).await?;
+    remember_directory(&app, &state, &path);
+
+    Ok(SaveResponse {
+        warning,
+        ..SaveResponse::saved(&path, Some(len as u64))
+    })
+}
+
+// Schema and rows of the current file as a SQL script. Rows are sorted by
+// primary key unless `deterministic` is false.
+#[tauri::command]
+async fn export_sql(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    deterministic: Option<bool>,
+) -> Result<String, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    let deterministic = deterministic.unwrap_or(true);
+    export::dump_sql(&conn, deterministic, &state.export_cancelled, export_progress(&app))
+}
+
+// Write the `export_sql` script to a file picked in the save dialog
+#[tauri::command]
+async fn export_sql_to_file(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    deterministic: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    let deterministic = deterministic.unwrap_or(true);
+    let script =
+        export::dump_sql(&conn, deterministic, &state.export_cancelled, export_progress(&app))?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let file_name = std::path::Path::new(&config.default_file_name).with_extension("sql");
+    let file_path = base_dialog(&app, &state)
+        .add_filter("SQL Script", &["sql"])
+        .set_file_name(file_name.to_string_lossy())
+        .blocking_save_file();
+
+    let path = dialog_path(file_path)?;
+    let len = script.len();
+    write_export(&state, &path, script.into_bytes()).await?;
+    remember_directory(&app, &state, &path);
+
+    Ok(SaveResponse::saved(&path, Some(len as u64)))
+}
+
+// Every table of the current file as a CSV inside one `.zip`, chosen with a
+// save dialog. The archive is streamed to disk rather than built in memory.
+#[tauri::command]
+async fn export_all_csv_zip(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let file_name = std::path::Path::new(&config.default_file_name).with_extension("zip");
+    let file_path = base_dialog(&app, &state)
+        .add_filter("Zip Archive", &["zip"])
+        .set_file_name(file_name.to_string_lossy())
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    ensure_not_current(&state, &target)?;
+    ensure_safe_location(&state, &target)?;
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    // An error, including cancellation, removes the temp file and leaves the
+    // target untouched
+    let result = storage::atomic_write_with(&target, |file| {
+        export::all_csv_zip(
+            &conn,
+            std::io::BufWriter::new(file),
+            &state.export_cancelled,
+            export_progress(&app),
+        )
+    })
+    .and_then(|_| Ok(std::fs::metadata(&target)?.len()));
+    let len = result.as_ref().map_or(0, |len| *len as usize);
+    log_operation(&state, "export csv zip", &target, len, &result);
+    let len = result?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SaveResponse::saved(&target, Some(len)))
+}
+
+// Save one table of the current file as newline-delimited JSON through the
+// save dialog. Rows go straight to the file, so huge tables don't fill memory.
+#[tauri::command]
+async fn export_table_ndjson(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    table: String,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    // The name is interpolated into SQL, so it must be a real table
+    if !sqlite::user_tables(&conn)?.contains(&table) {
+        return Err(BrewError::UnknownTable(table));
+    }
+
+    let file_path = base_dialog(&app, &state)
+        .add_filter("Newline-Delimited JSON", &["ndjson"])
+        .set_file_name(format!("{}.ndjson", table))
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    ensure_not_current(&state, &target)?;
+    ensure_safe_location(&state, &target)?;
+    state.export_cancelled.store(false, Ordering::SeqCst);
+    let result = storage::atomic_write_with(&target, |file| {
+        export::table_ndjson(&conn, &table, std::io::BufWriter::new(file), &state.export_cancelled)
+    })
+    .and_then(|_| Ok(std::fs::metadata(&target)?.len()));
+    let len = result.as_ref().map_or(0, |len| *len as usize);
+    log_operation(&state, "export ndjson", &target, len, &result);
+    let len = result?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SaveResponse::saved(&target, Some(len)))
+}
+
+// Rows of one table in the current file as a JSON array of objects
+#[tauri::command]
+async fn export_table_json(state: State<'_, AppState>, table: String) -> Result<String, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    // The name is interpolated into SQL, so it must be a real table
+    if !sqlite::user_tables(&conn)?.contains(&table) {
+        return Err(BrewError::UnknownTable(table));
+    }
+    Ok(export::table_json(&conn, &table)?)
+}
+
+// Import rows from a CSV file into `table` of the current database file.
+// Goes through the live connection when one is open. The file on disk
+// changes, so the frontend should reload it afterwards.
+#[tauri::command]
+async fn import_csv(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    table: String,
+    csv_path: String,
+    has_header: bool,
+) -> Result<import::ImportReport, BrewError> {
+    let path = current_path(&state)?;
+    ensure_writable(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+    let max_failed_rows = state.settings.lock().unwrap().import_max_failed_rows;
+    let file = std::fs::File::open(storage::path_from_key(&csv_path))?;
+
+    let result = {
+        let mut live = state.live_connection.lock().unwrap();
+        match live.as_mut() {
+            Some(conn) => import::import_csv(conn, &table, file, has_header, max_failed_rows),
+            None => sqlite::open_read_write(&path)
+                .map_err(BrewError::from)
+                .and_then(|mut conn| {
+                    import::import_csv(&mut conn, &table, file, has_header, max_failed_rows)
+                }),
+        }
+    };
+    record_activity(&state, "import csv", &path, &result);
+    let report = result?;
+
+    // Our own write shouldn't show up as an external change
+    let path_ref = path.as_path();
+    record_disk_stamp(&state, path_ref);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(path_ref)?);
+    set_dirty(&app, &state, true);
+    Ok(report)
+}
+
+// Copy the rows of every table shared with the database at `other_path` into
+// the current file. The frontend should reload the file afterwards.
+#[tauri::command]
+async fn merge_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    other_path: String,
+    conflict: merge::MergeStrategy,
+) -> Result<merge::MergeReport, BrewError> {
+    let path = current_path(&state)?;
+    ensure_writable(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+    let other = &storage::path_from_key(&other_path);
+
+    let result = {
+        let mut live = state.live_connection.lock().unwrap();
+        match live.as_mut() {
+            Some(conn) => merge::merge(conn, other, conflict),
+            None => sqlite::open_read_write(&path)
+                .map_err(BrewError::from)
+                .and_then(|mut conn| merge::merge(&mut conn, other, conflict)),
+        }
+    };
+    record_activity(&state, "merge", &path, &result);
+    let report = result?;
+
+    let path_ref = path.as_path();
+    record_disk_stamp(&state, path_ref);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(path_ref)?);
+    set_dirty(&app, &state, true);
+    Ok(report)
+}
+
+// How many rows `import_csv` may skip before it rolls the whole import back
+#[tauri::command]
+async fn configure_import(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    max_failed_rows: u64,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        import_max_failed_rows: Some(max_failed_rows),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Write the bytes to a fresh file in the OS temp directory, e.g. to hand to
+// an external viewer. Doesn't touch the current document. The file is
+// deleted by `cleanup_temp_files`, which also runs on exit.
+#[tauri::command]
+async fn save_temp(state: State<'_, AppState>, data: Vec<u8>) -> Result<String, BrewError> {
+    let path = storage::write_temp_file(&data, "db")?;
+    let path_str = path_string(&path);
+    state.temp_files.lock().unwrap().push(path);
+    Ok(path_str)
+}
+
+// Rename the current file within its folder. Backups, the lock, recent
+// files, view state and passphrase hint follow it to the new name.
+#[tauri::command]
+async fn rename_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    new_name: String,
+    overwrite: bool,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let new_name = new_name.trim();
+    let extensions = state.dialog_config.lock().unwrap().extensions.clone();
+    if new_name.is_empty() || new_name.contains(['/', '\\']) || new_name == ".." {
+        return Err(BrewError::InvalidFileName(
+            "the name can't be empty or contain path separators".to_string(),
+        ));
+    }
+    let old = path.as_path();
+    let new = old.with_file_name(new_name);
+    if !extensions.is_empty()
+        && !new
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+    {
+        return Err(BrewError::InvalidFileName(format!(
+            "the name must end in .{}",
+            extensions.join(" or .")
+        )));
+    }
+    if new == old {
+        return Ok(SaveResponse::saved(old, None));
+    }
+
+    // A case-only rename on a case-insensitive disk finds the file itself
+    let new_id = storage::file_id(&new).ok().flatten();
+    let same_file = new_id.is_some() && new_id == storage::file_id(old).ok().flatten();
+    if new.exists() && !same_file && !overwrite {
+        return Err(BrewError::TargetExists(new.display().to_string()));
+    }
+    ensure_unlocked(&new)?;
+
+    // Windows can't rename a file SQLite still has open
+    *state.live_connection.lock().unwrap() = None;
+    let result = std::fs::rename(old, &new).map_err(BrewError::from);
+    record_activity(&state, "rename", old, &result);
+    result?;
+    let backup_count = state.settings.lock().unwrap().backup_count;
+    for index in 0..backup_count {
+        let backup = storage::backup_path(old, index);
+        if backup.exists() {
+            if let Err(e) = std::fs::rename(&backup, storage::backup_path(&new, index)) {
+                log::warn!("Failed to move backup {}: {}", backup.display(), e);
+            }
+        }
+    }
+    let sidecar = storage::checksum_sidecar_path(old);
+    if sidecar.exists() {
+        if let Err(e) = std::fs::rename(&sidecar, storage::checksum_sidecar_path(&new)) {
+            log::warn!("Failed to move checksum sidecar {}: {}", sidecar.display(), e);
+        }
+    }
+
+    swap_lock(&state, &new);
+    record_disk_stamp(&state, &new);
+    set_current_path(&app, &state, Some(new.clone()));
+    let old_key = path_string(old);
+    let new_key = path_string(&new);
+    state.recent_files.lock().unwrap().retain(|entry| entry.path != old_key);
+    remember_recent(&app, &state, &new);
+    {
+        let mut states = state.view_states.lock().unwrap();
+        if let Some(view) = states.remove(&old_key) {
+            states.insert(new_key.clone(), view);
+            store_config(&app, VIEW_STATES_FILE, &*states);
+        }
+    }
+    {
+        let mut hints = state.password_hints.lock().unwrap();
+        if let Some(hint) = hints.remove(&old_key) {
+            hints.insert(new_key.clone(), hint);
+            store_config(&app, PASSWORD_HINTS_FILE, &*hints);
+        }
+    }
+    log::info!("Renamed {} to {}", old.display(), new.display());
+
+    Ok(SaveResponse::saved(&new, None))
+}
+
+// Delete every file made by `save_temp`. Files another program still holds
+// open (Windows) are kept for the next attempt.
+fn remove_temp_files(state: &AppState) {
+    state.temp_files.lock().unwrap().retain(|path| match std::fs::remove_file(path) {
+        Ok(()) => false,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+        Err(e) => {
+            log::warn!("Failed to remove temp file {}: {}", path.display(), e);
+            true
+        }
+    });
+}
+
+#[tauri::command]
+async fn cleanup_temp_files(state: State<'_, AppState>) -> Result<(), BrewError> {
+    remove_temp_files(&state);
+    Ok(())
+}
+
+// Temp, backup or lock file brewcode left in the current database's folder
+#[derive(Serialize)]
+struct StrayFile {
+    path: String,
+    path_display: String,
+    kind: storage::StrayKind,
+    // File name of the database it was made for, which may no longer exist
+    database: String,
+    size_bytes: u64,
+    modified: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct PruneReport {
+    deleted: Vec<String>,
+    skipped: Vec<String>,
+    bytes_reclaimed: u64,
+}
+
+// `path` as a stray file if its name follows our conventions and deleting it
+// can't hurt: never the current or a recent database, a temp file a running
+// save may still be writing, or a lock another live process holds
+fn stray_file(state: &AppState, path: &std::path::Path) -> Option<StrayFile> {
+    let name = path.file_name()?.to_str()?;
+    let (kind, database) = storage::stray_kind(name)?;
+    let metadata = std::fs::symlink_metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    if state.current_db_path.lock().unwrap().as_deref() == Some(path) {
+        return None;
+    }
+    let key = path_string(path);
+    if state.recent_files.lock().unwrap().iter().any(|entry| entry.path == key) {
+        return None;
+    }
+    let in_use = match kind {
+        storage::StrayKind::Temp => storage::temp_file_pid(name)
+            .is_some_and(|pid| pid == std::process::id() || lock::process_alive(pid)),
+        storage::StrayKind::Lock => {
+            lock::read_lock_file(path).map_or(true, |info| lock::process_alive(info.pid))
+        }
+        storage::StrayKind::Backup => false,
+    };
+    if in_use {
+        return None;
+    }
+
+    Some(StrayFile {
+        path: key,
+        path_display: path_display(path),
+        kind,
+        database: database.to_string(),
+        size_bytes: metadata.len(),
+        modified: metadata.modified().ok().and_then(unix_millis),
+    })
+}
+
+// Folder holding the current database
+fn current_dir(state: &AppState) -> Result<PathBuf, BrewError> {
+    let path = current_path(state)?;
+    Ok(path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), std::path::Path::to_path_buf))
+}
+
+// Leftover temp files, backups and stale locks next to the current database.
+// Only files named the way brewcode names them are listed.
+#[tauri::command]
+async fn list_stray_files(state: State<'_, AppState>) -> Result<Vec<StrayFile>, BrewError> {
+    let dir = current_dir(&state)?;
+    let mut strays = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        if let Some(stray) = entry.ok().and_then(|entry| stray_file(&state, &entry.path())) {
+            strays.push(stray);
+        }
+    }
+    strays.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(strays)
+}
+
+// Delete files picked from `list_stray_files`. Each path is checked again, so
+// anything outside the current folder or not one of our leftovers is skipped.
+#[tauri::command]
+async fn prune_stray_files(
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+) -> Result<PruneReport, BrewError> {
+    let dir = current_dir(&state)?;
+    let mut report = PruneReport {
+        deleted: Vec::new(),
+        skipped: Vec::new(),
+        bytes_reclaimed: 0,
+    };
+    for path_str in paths {
+        let path = storage::path_from_key(&path_str);
+        let stray = match path.parent() {
+            Some(parent) if parent == dir => stray_file(&state, &path),
+            _ => None,
+        };
+        let Some(stray) = stray else {
+            report.skipped.push(path_str);
+            continue;
+        };
+        let result = std::fs::remove_file(&path).map_err(BrewError::from);
+        record_activity(&state, "prune", &path, &result);
+        match result {
+            Ok(()) => {
+                report.bytes_reclaimed += stray.size_bytes;
+                report.deleted.push(path_str);
+            }
+            Err(_) => report.skipped.push(path_str),
+        }
+    }
+    Ok(report)
+}
+
+// Copy the current file to `<name> copy.db` alongside it. The copy is
+// byte-for-byte and does not become the current database.
+#[tauri::command]
+async fn duplicate_database(state: State<'_, AppState>) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let copy = storage::next_copy_path(&path);
+    let copied = std::fs::copy(&path, &copy)?;
+
+    Ok(SaveResponse::saved(&copy, Some(copied)))
+}
+
+// Forget everything about the current document
+fn close_document(app: &tauri::AppHandle, state: &AppState) {
+    *state.live_connection.lock().unwrap() = None;
+    if let Some(path) = state.current_db_path.lock().unwrap().as_ref() {
+        lock::release(std::path::Path::new(path));
+    }
+    set_current_path(app, state, None);
+    set_dirty(app, state, false);
+    clear_journal(app);
+    *state.disk_stamp.lock().unwrap() = None;
+    *state.read_only.lock().unwrap() = false;
+    *state.saved_checksum.lock().unwrap() = None;
+    *state.encrypted.lock().unwrap() = false;
+    state.undo_stack.lock().unwrap().clear();
+    state.redo_stack.lock().unwrap().clear();
+}
+
+// Start a new, unsaved database with the default schema. There's no path
+// until the first save, which therefore goes through Save As.
+#[tauri::command]
+async fn new_database(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<u8>, BrewError> {
+    let data = migrations::new_database()?;
+    close_document(&app, &state);
+    set_dirty(&app, &state, true);
+    Ok(data)
+}
+
+// The current database as base64 text, for pasting into a link or a chat.
+// Capped at `MAX_BASE64_BYTES` so nobody ends up with a huge clipboard.
+#[tauri::command]
+async fn export_base64(state: State<'_, AppState>) -> Result<String, BrewError> {
+    use base64::Engine;
+
+    let path = current_path(&state)?;
+    // A file already over the cap would only be over it once decompressed too
+    if std::fs::metadata(&path)?.len() > MAX_BASE64_BYTES {
+        return Err(BrewError::PayloadTooLarge { limit: MAX_BASE64_BYTES });
+    }
+    let data = decode_database(std::fs::read(&path)?, false, open_limit(&state, false))?;
+    if data.len() as u64 > MAX_BASE64_BYTES {
+        return Err(BrewError::PayloadTooLarge { limit: MAX_BASE64_BYTES });
+    }
+    Ok(base64::engine::general_purpose::STANDARD.encode(data))
+}
+
+// Load a database from `export_base64` text as a new, unsaved document.
+// Whitespace is ignored, since pasted text is often wrapped.
+#[tauri::command]
+async fn import_base64(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: String,
+) -> Result<OpenResponse, BrewError> {
+    use base64::Engine;
+
+    let text: String = data.split_whitespace().collect();
+    // Four characters per three bytes
+    if text.len() as u64 > MAX_BASE64_BYTES.div_ceil(3) * 4 {
+        return Err(BrewError::PayloadTooLarge { limit: MAX_BASE64_BYTES });
+    }
+    let raw = base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .map_err(|e| BrewError::InvalidDatabase(format!("the text isn't valid base64: {}", e)))?;
+    let size = raw.len() as u64;
+    let was_compressed = storage::is_gzip(&raw);
+    let data = decode_database(raw, false, open_limit(&state, false))?;
+
+    close_document(&app, &state);
+    set_dirty(&app, &state, true);
+    Ok(OpenResponse {
+        data,
+        path: None,
+        path_display: UNSAVED_DISPLAY_NAME.to_string(),
+        size,
+        was_compressed,
+        was_encrypted: false,
+        checksum_mismatch: false,
+    })
+}
+
+// A ready-made database offered by the app, from `templates.json`
+#[derive(Serialize, Deserialize)]
+struct TemplateInfo {
+    id: String,
+    name: String,
+    description: String,
+    // Relative to the templates folder; not sent to the frontend. Templates
+    // without a file come from `builtin_template`.
+    #[serde(default, skip_serializing)]
+    file: Option<String>,
+}
+
+// Templates made from the migrations instead of bundled as files, so they
+// always match the current schema
+fn builtin_template(id: &str) -> Option<Result<Vec<u8>, BrewError>> {
+    match id {
+        "starter" => Some(migrations::new_database()),
+        "blank" => Some(migrations::empty_database()),
+        _ => None,
+    }
+}
+
+fn template_manifest(app: &tauri::AppHandle) -> Result<(PathBuf, Vec<TemplateInfo>), BrewError> {
+    let manifest = app
+        .path()
+        .resolve(TEMPLATES_MANIFEST, tauri::path::BaseDirectory::Resource)
+        .map_err(|e| BrewError::Io(std::io::Error::other(e.to_string())))?;
+    let file = std::fs::File::open(&manifest)?;
+    let templates = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| BrewError::Io(std::io::Error::other(e.to_string())))?;
+    let dir = manifest.parent().map(PathBuf::from).unwrap_or_default();
+    Ok((dir, templates))
+}
+
+#[tauri::command]
+async fn list_templates(app: tauri::AppHandle) -> Result<Vec<TemplateInfo>, BrewError> {
+    Ok(template_manifest(&app)?.1)
+}
+
+// Start a new, unsaved document from the template `id`. For a bundled file
+// only the bytes are used; the resource itself is never opened for writing.
+#[tauri::command]
+async fn new_from_template(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<OpenResponse, BrewError> {
+    let (dir, templates) = template_manifest(&app)?;
+    let template = templates
+        .into_iter()
+        .find(|template| template.id == id)
+        .ok_or(BrewError::UnknownTemplate(id))?;
+    let raw = match &template.file {
+        Some(file) => std::fs::read(dir.join(file))?,
+        None => builtin_template(&template.id)
+            .ok_or_else(|| BrewError::UnknownTemplate(template.id.clone()))??,
+    };
+    let size = raw.len() as u64;
+    let was_compressed = storage::is_gzip(&raw);
+    let data = decode_database(raw, false, open_limit(&state, false))?;
+
+    close_document(&app, &state);
+    set_dirty(&app, &state, true);
+    Ok(OpenResponse {
+        data,
+        path: None,
+        path_display: UNSAVED_DISPLAY_NAME.to_string(),
+        size,
+        was_compressed,
+        was_encrypted: false,
+        checksum_mismatch: false,
+    })
+}
+
+// Close the current database; the file on disk is left alone
+#[tauri::command]
+async fn close_database(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), BrewError> {
+    close_document(&app, &state);
+    Ok(())
+}
+
+// Slot id the single-document commands work on. The current document lives
+// directly in `AppState`; every other slot is a `DocumentState` in `slots`.
+const DEFAULT_SLOT: &str = "default";
+
+// What we track for a document open in a slot other than the default one
+struct DocumentState {
+    path: PathBuf,
+    read_only: bool,
+    disk_stamp: Option<storage::FileStamp>,
+}
+
+#[derive(Serialize)]
+struct SlotInfo {
+    id: String,
+    path: Option<String>,
+    path_display: String,
+    read_only: bool,
+}
+
+// Refuse to open one file in two slots: they'd share its lock and overwrite
+// each other's saves
+fn ensure_not_open(state: &AppState, id: &str, path: &std::path::Path) -> Result<(), BrewError> {
+    let in_default = id != DEFAULT_SLOT
+        && state.current_db_path.lock().unwrap().as_deref() == Some(path);
+    let in_other = state
+        .slots
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(slot, document)| slot != id && document.path == path);
+    if in_default || in_other {
+        return Err(BrewError::AlreadyOpen(path_display(path)));
+    }
+    Ok(())
+}
+
+// Open the database at `path` into slot `id`, replacing whatever the slot
+// held. The default slot is the current document, as with `open_database_at`.
+#[tauri::command]
+async fn open_into_slot(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+    path: String,
+    force: bool,
+    allow_large: Option<bool>,
+) -> Result<OpenResponse, BrewError> {
+    let path = storage::path_from_key(&path);
+    ensure_not_open(&state, &id, &path)?;
+    let allow_large = allow_large.unwrap_or(false);
+    if id == DEFAULT_SLOT {
+        return load_database(&app, &state, &path, force, allow_large);
+    }
+
+    let result = read_bounded(&state, &path, allow_large).and_then(|raw| {
+        let size = raw.len() as u64;
+        let was_compressed = storage::is_gzip(&raw);
+        let checksum_mismatch = storage::read_checksum_sidecar(&path)
+            .is_some_and(|(digest, len)| len != size || digest != storage::sha256_hex(&raw));
+        let data = decode_database(raw, force, open_limit(&state, allow_large))?;
+        ensure_unlocked(&path)?;
+        let document = DocumentState {
+            path: path.clone(),
+            read_only: storage::is_read_only(&path)?,
+            disk_stamp: storage::FileStamp::of(&path).ok(),
+        };
+        let response = OpenResponse {
+            data,
+            path: Some(path_string(&path)),
+            path_display: path_display(&path),
+            size,
+            was_compressed,
+            was_encrypted: false,
+            checksum_mismatch,
+        };
+        Ok((document, response))
+    });
+    let len = result.as_ref().map_or(0, |(_, response)| response.data.len());
+    log_operation(&state, "open", &path, len, &result);
+    let (document, response) = result?;
+
+    if let Err(e) = lock::acquire(&path, unix_millis(std::time::SystemTime::now())) {
+        log::warn!("Failed to write lock file for {}: {}", path.display(), e);
+    }
+    let previous = state.slots.lock().unwrap().insert(id, document);
+    if let Some(previous) = previous.filter(|previous| previous.path != path) {
+        lock::release(&previous.path);
+    }
+    remember_recent(&app, &state, &path);
+    Ok(response)
+}
+
+// Save `data` over the file open in slot `id`, rotating backups first. Like
+// `save_database`, a file replaced on disk is only overwritten with `force`.
+#[tauri::command]
+async fn save_slot(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+    data: Vec<u8>,
+    force: bool,
+    skip_validation: Option<bool>,
+) -> Result<SaveResponse, BrewError> {
+    if id == DEFAULT_SLOT {
+        return save_database(app, state, data, force, skip_validation).await;
+    }
+    validate_save_data(&data, skip_validation)?;
+    let (path, read_only, stamp) = {
+        let slots = state.slots.lock().unwrap();
+        let document = slots.get(&id).ok_or_else(|| BrewError::UnknownSlot(id.clone()))?;
+        (document.path.clone(), document.read_only, document.disk_stamp)
+    };
+    if read_only {
+        return Err(BrewError::ReadOnly);
+    }
+    if !force {
+        ensure_same_file_as(stamp, &path)?;
+    }
+
+    let backup_count = state.settings.lock().unwrap().backup_count;
+    let len = data.len();
+    let written = match ensure_free_space(&path, len) {
+        Ok(()) => write_with_events(&app, &state, &path, data, backup_count).await,
+        Err(e) => Err(e),
+    };
+    log_operation(&state, "save", &path, len, &written);
+    written?;
+    let mirror = write_mirror(&state, &path);
+    // The slot may have been closed while the write ran
+    if let Some(document) = state.slots.lock().unwrap().get_mut(&id) {
+        document.disk_stamp = storage::FileStamp::of(&path).ok();
+    }
+    remember_recent(&app, &state, &path);
+
+    Ok(SaveResponse::saved(&path, Some(len as u64)).mirrored(mirror))
+}
+
+// Close the document in slot `id`; the file on disk is left alone
+#[tauri::command]
+async fn close_slot(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<(), BrewError> {
+    if id == DEFAULT_SLOT {
+        close_document(&app, &state);
+        return Ok(());
+    }
+    let document = state.slots.lock().unwrap().remove(&id);
+    let document = document.ok_or(BrewError::UnknownSlot(id))?;
+    lock::release(&document.path);
+    Ok(())
+}
+
+// Every slot with a file open, the default one first
+#[tauri::command]
+async fn list_slots(state: State<'_, AppState>) -> Result<Vec<SlotInfo>, BrewError> {
+    let mut slots: Vec<SlotInfo> = state
+        .current_db_path
+        .lock()
+        .unwrap()
+        .as_deref()
+        .map(|path| SlotInfo {
+            id: DEFAULT_SLOT.to_string(),
+            path: Some(path_string(path)),
+            path_display: path_display(path),
+            read_only: *state.read_only.lock().unwrap(),
+        })
+        .into_iter()
+        .collect();
+    let mut others: Vec<SlotInfo> = state
+        .slots
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, document)| SlotInfo {
+            id: id.clone(),
+            path: Some(path_string(&document.path)),
+            path_display: path_display(&document.path),
+            read_only: document.read_only,
+        })
+        .collect();
+    others.sort_by(|a, b| a.id.cmp(&b.id));
+    slots.extend(others);
+    Ok(slots)
+}
+
+// Delete the current database file from disk and close it. `confirm` must be
+// true so a stray invoke can't remove the user's data.
+#[tauri::command]
+async fn delete_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    confirm: bool,
+) -> Result<(), BrewError> {
+    if !confirm {
+        return Err(BrewError::ConfirmationRequired);
+    }
+
+    let path = current_path(&state)?;
+    // Windows refuses to delete a file that's still open
+    *state.live_connection.lock().unwrap() = None;
+    let result = std::fs::remove_file(&path).map_err(BrewError::from);
+    record_activity(&state, "delete", &path, &result);
+    result?;
+    close_document(&app, &state);
+    Ok(())
+}
+
+// Whether another running brewcode holds the lock on `path` (default: the
+// current database). Locks left by crashed instances don't count.
+#[tauri::command]
+async fn is_locked_elsewhere(
+    state: State<'_, AppState>,
+    path: Option<String>,
+) -> Result<bool, BrewError> {
+    let path = match path {
+        Some(path) => storage::path_from_key(&path),
+        None => current_path(&state)?,
+    };
+    Ok(lock::held_elsewhere(&path).is_some())
+}
+
+// Delete the lock file on `path` (default: the current database) regardless
+// of who owns it, for locks the staleness check can't see through
+#[tauri::command]
+async fn force_unlock(state: State<'_, AppState>, path: Option<String>) -> Result<(), BrewError> {
+    let path = match path {
+        Some(path) => storage::path_from_key(&path),
+        None => current_path(&state)?,
+    };
+    Ok(lock::force_release(&path)?)
+}
+
+// What brewcode did this session, oldest first. Not persisted; the log file
+// from `get_log_path` has the full history.
+#[tauri::command]
+async fn get_activity_log(state: State<'_, AppState>) -> Result<Vec<ActivityEntry>, BrewError> {
+    Ok(state.activity.lock().unwrap().iter().cloned().collect())
+}
+
+// The last failed operation recorded in the activity log, if it hasn't
+// since succeeded
+#[tauri::command]
+async fn last_error(state: State<'_, AppState>) -> Result<Option<ErrorDetails>, BrewError> {
+    Ok(state.last_error.lock().unwrap().clone())
+}
+
+#[tauri::command]
+async fn clear_activity_log(state: State<'_, AppState>) -> Result<(), BrewError> {
+    state.activity.lock().unwrap().clear();
+    Ok(())
+}
+
+// Settings from settings.json merged over the defaults. Without one, the
+// older standalone reopen-last file is still honoured.
+fn load_settings(app: &tauri::AppHandle) -> settings::Settings {
+    load_config(app, SETTINGS_FILE).unwrap_or_else(|| settings::Settings {
+        reopen_last_on_launch: load_config(app, LEGACY_REOPEN_LAST_FILE).unwrap_or(false),
+        ..Default::default()
+    })
+}
+
+// Make `new` the active settings: restart or stop autosave, trim undo
+// snapshots and drop the crash journal as needed, then persist them
+fn commit_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    new: settings::Settings,
+) -> settings::Settings {
+    let old = std::mem::replace(&mut *state.settings.lock().unwrap(), new.clone());
+    if old.autosave_interval != new.autosave_interval {
+        restart_autosave(app, state, new.autosave_interval);
+    }
+    if new.snapshot_depth < old.snapshot_depth {
+        trim_snapshots(&mut state.undo_stack.lock().unwrap(), new.snapshot_depth);
+    }
+    if old.journal_enabled && !new.journal_enabled {
+        clear_journal(app);
+    }
+    store_config(app, SETTINGS_FILE, &new);
+    new
+}
+
+fn update_settings_with(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    partial: settings::PartialSettings,
+) -> settings::Settings {
+    let merged = state.settings.lock().unwrap().merged(partial);
+    commit_settings(app, state, merged)
+}
+
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> Result<settings::Settings, BrewError> {
+    Ok(state.settings.lock().unwrap().clone())
+}
+
+// Change the settings `partial` names and return the full result
+#[tauri::command]
+async fn update_settings(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    partial: settings::PartialSettings,
+) -> Result<settings::Settings, BrewError> {
+    Ok(update_settings_with(&app, &state, partial))
+}
+
+// Put every setting back to its default
+#[tauri::command]
+async fn reset_settings(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<settings::Settings, BrewError> {
+    Ok(commit_settings(&app, &state, settings::Settings::default()))
+}
+
+// Change the file type and default name used by the open/save/export dialogs
+#[tauri::command]
+async fn configure_dialogs(
+    state: State<'_, AppState>,
+    config: DialogConfig,
+) -> Result<(), BrewError> {
+    *state.dialog_config.lock().unwrap() = config;
+    Ok(())
+}
+
+// Read one of the rolling backups of the current database (0 = most recent)
+#[tauri::command]
+async fn restore_backup(
+    state: State<'_, AppState>,
+    index: u8,
+) -> Result<Vec<u8>, BrewError> {
+    let path = current_path(&state)?;
+
+    let backup = storage::backup_path(&path, index);
+    if index >= state.settings.lock().unwrap().backup_count || !backup.exists() {
+        return Err(BrewError::BackupNotFound(index));
+    }
+
+    read_bounded(&state, &backup, false)
+}
+
+// Rows in one table of the current file and of a backup; None where the
+// table doesn't exist
+#[derive(Serialize)]
+struct TableCounts {
+    table: String,
+    current: Option<u64>,
+    backup: Option<u64>,
+}
+
+// Result of `verify_backup`. `row_counts` is only filled in when the files
+// differ and both can be read as plain databases.
+#[derive(Serialize)]
+struct BackupVerification {
+    identical: bool,
+    row_counts: Option<Vec<TableCounts>>,
+}
+
+fn table_row_counts(path: &std::path::Path) -> Option<HashMap<String, u64>> {
+    let conn = sqlite::open_read_only(path).ok()?;
+    let tables = sqlite::schema(&conn).ok()?;
+    Some(tables.into_iter().map(|table| (table.name, table.row_count)).collect())
+}
+
+// Compare one of the rolling backups with the current file byte for byte
+#[tauri::command]
+async fn verify_backup(
+    state: State<'_, AppState>,
+    index: u8,
+) -> Result<BackupVerification, BrewError> {
+    let path = current_path(&state)?;
+
+    let backup = storage::backup_path(&path, index);
+    if index >= state.settings.lock().unwrap().backup_count || !backup.exists() {
+        return Err(BrewError::BackupNotFound(index));
+    }
+
+    let identical = storage::sha256_file(&path)? == storage::sha256_file(&backup)?;
+    let row_counts = if identical {
+        None
+    } else {
+        table_row_counts(&path).zip(table_row_counts(&backup)).map(|(current, old)| {
+            let mut tables: Vec<&String> = current.keys().chain(old.keys()).collect();
+            tables.sort();
+            tables.dedup();
+            tables
+                .into_iter()
+                .map(|table| TableCounts {
+                    table: table.clone(),
+                    current: current.get(table).copied(),
+                    backup: old.get(table).copied(),
+                })
+                .collect()
+        })
+    };
+
+    Ok(BackupVerification { identical, row_counts })
+}
+
+// What a temp strategy means for the user's files, returned by
+// `configure_temp_strategy`
+#[derive(Serialize)]
+struct TempStrategyInfo {
+    strategy: storage::TempStrategy,
+    atomic: bool,
+    behavior: &'static str,
+}
+
+// Choose where saves stage their temp file. `SameDir` keeps saves atomic;
+// `SystemTemp` avoids the database folder at the cost of atomicity.
+#[tauri::command]
+async fn configure_temp_strategy(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    strategy: storage::TempStrategy,
+) -> Result<TempStrategyInfo, BrewError> {
+    let partial = settings::PartialSettings {
+        temp_strategy: Some(strategy),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(match strategy {
+        storage::TempStrategy::SameDir => TempStrategyInfo {
+            strategy,
+            atomic: true,
+            behavior: "Saves are written next to the database and renamed over it; an interrupted save leaves the old file intact.",
+        },
+        storage::TempStrategy::SystemTemp => TempStrategyInfo {
+            strategy,
+            atomic: false,
+            behavior: "Saves are written to the system temp folder, then copied over the database if it is on another drive. An interrupted copy can leave the database truncated; backups are still rotated first.",
+        },
+    })
+}
+
+// How much saves fsync: `Full` (file and folder, the default), `Relaxed`
+// (file only) or `None`. With `None` a crash or power cut shortly after a
+// save can leave the database empty or corrupt, so it's only for slow
+// network mounts where the user keeps other copies.
+#[tauri::command]
+async fn configure_durability(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    durability: storage::Durability,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        durability: Some(durability),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Whether launching brewcode without a file reopens the most recent one
+#[tauri::command]
+async fn configure_reopen_last(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        reopen_last_on_launch: Some(enabled),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Let saves and exports go into app and system folders; off by default
+#[tauri::command]
+async fn configure_safe_mode(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    allow_unsafe_location: bool,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        allow_unsafe_location: Some(allow_unsafe_location),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Seconds a save or export may take before failing with `Timeout`
+#[tauri::command]
+async fn configure_save_timeout(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    secs: u64,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        save_timeout_secs: Some(secs),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Set how many rolling backups are kept when saving over an existing file
+#[tauri::command]
+async fn configure_backups(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    count: u8,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        backup_count: Some(count),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Whether the current file is write-protected on disk; `save_database` refuses these
+#[tauri::command]
+async fn is_read_only(state: State<'_, AppState>) -> Result<bool, BrewError> {
+    Ok(*state.read_only.lock().unwrap())
+}
+
+// Record that the in-memory database has edits not yet written to disk
+#[tauri::command]
+async fn mark_dirty(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    data: Option<Vec<u8>>,
+) -> Result<(), BrewError> {
+    set_dirty(&app, &state, true);
+    if let Some(data) = data {
+        write_journal(&app, &state, &data);
+    }
+    Ok(())
+}
+
+// Opt in to caching unsaved bytes from `mark_dirty` for crash recovery.
+// Edits larger than `max_bytes` aren't cached.
+#[tauri::command]
+async fn configure_journal(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+    max_bytes: Option<u64>,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        journal_enabled: Some(enabled),
+        journal_max_bytes: max_bytes,
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// The recovery found at startup, if any, for a frontend that missed
+// `recover-available`. Only returned once.
+#[tauri::command]
+async fn take_recovery(state: State<'_, AppState>) -> Result<Option<Recovery>, BrewError> {
+    Ok(state.recovery.lock().unwrap().take())
+}
+
+// Drop the cached unsaved bytes after the user recovered or declined them
+#[tauri::command]
+async fn discard_recovery(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), BrewError> {
+    *state.recovery.lock().unwrap() = None;
+    clear_journal(&app);
+    Ok(())
+}
+
+// Check for unsaved changes (used by the window close handler). When the
+// current bytes are passed, compare their checksum against the last save
+// instead of trusting the flag.
+#[tauri::command]
+async fn is_dirty(state: State<'_, AppState>, data: Option<Vec<u8>>) -> Result<bool, BrewError> {
+    let saved = state.saved_checksum.lock().unwrap();
+    match (data, saved.as_ref()) {
+        (Some(data), Some(saved)) => Ok(storage::sha256_hex(&data) != *saved),
+        _ => Ok(*state.dirty.lock().unwrap()),
+    }
+}
+
+// SHA-256 hex digest of the given bytes
+#[tauri::command]
+async fn compute_checksum(data: Vec<u8>) -> Result<String, BrewError> {
+    Ok(storage::sha256_hex(&data))
+}
+
+// Recently opened or saved databases that still exist, most recent first
+#[tauri::command]
+async fn get_recent_files(state: State<'_, AppState>) -> Result<Vec<String>, BrewError> {
+    Ok(state
+        .recent_files
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|entry| storage::path_from_key(&entry.path).exists())
+        .map(|entry| entry.path.clone())
+        .collect())
+}
+
+// Every recent file with its size and a header check, including files that
+// no longer exist, in one call for the dashboard
+#[tauri::command]
+async fn recent_files_detailed(
+    state: State<'_, AppState>,
+) -> Result<Vec<RecentFileInfo>, BrewError> {
+    let recent = state.recent_files.lock().unwrap().clone();
+    Ok(recent
+        .into_iter()
+        .map(|entry| {
+            let path = storage::path_from_key(&entry.path);
+            let metadata = std::fs::metadata(&path).ok();
+            RecentFileInfo {
+                path_display: path_display(&path),
+                exists: metadata.is_some(),
+                size_bytes: metadata.map(|metadata| metadata.len()),
+                valid_sqlite: has_sqlite_header_on_disk(&path),
+                last_opened: entry.last_opened,
+                path: entry.path,
+            }
+        })
+        .collect())
+}
+
+// Drop one entry from the recent-files list, e.g. a file that was deleted
+#[tauri::command]
+async fn remove_recent_file(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<(), BrewError> {
+    let mut recent = state.recent_files.lock().unwrap();
+    recent.retain(|entry| entry.path != path);
+    store_config(&app, RECENT_FILES_FILE, &*recent);
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_recent_files(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), BrewError> {
+    let mut recent = state.recent_files.lock().unwrap();
+    recent.clear();
+    store_config(&app, RECENT_FILES_FILE, &*recent);
+    Ok(())
+}
+
+// Remember where the user is in the current database
+#[tauri::command]
+async fn save_view_state(
+    app: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    state: ViewState,
+) -> Result<(), BrewError> {
+    let path = path_string(&current_path(&app_state)?);
+    let mut states = app_state.view_states.lock().unwrap();
+    states.insert(path, state);
+    store_config(&app, VIEW_STATES_FILE, &*states);
+    Ok(())
+}
+
+// The view state last saved for the current database, if any
+#[tauri::command]
+async fn load_view_state(app_state: State<'_, AppState>) -> Result<Option<ViewState>, BrewError> {
+    let path = path_string(&current_path(&app_state)?);
+    Ok(app_state.view_states.lock().unwrap().get(&path).cloned())
+}
+
+// Store a reminder for the current database's passphrase in the app config,
+// never in the file itself. An empty hint removes it. A hint that decrypts
+// the file is the passphrase and is refused.
+#[tauri::command]
+async fn set_password_hint(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    hint: String,
+) -> Result<(), BrewError> {
+    let path = current_path(&state)?;
+    let hint = hint.trim().to_string();
+    if hint.chars().count() > MAX_HINT_CHARS {
+        return Err(BrewError::InvalidHint(format!(
+            "hints are limited to {} characters",
+            MAX_HINT_CHARS
+        )));
+    }
+
+    if !hint.is_empty() {
+        let data = read_bounded(&state, &path, false)?;
+        if crypto::is_encrypted(&data) && crypto::decrypt(&data, &hint).is_ok() {
+            return Err(BrewError::InvalidHint(
+                "the hint can't be the passphrase itself".to_string(),
+            ));
+        }
+    }
+
+    let key = path_string(&path);
+    let mut hints = state.password_hints.lock().unwrap();
+    if hint.is_empty() {
+        hints.remove(&key);
+    } else {
+        hints.insert(key, hint);
+    }
+    store_config(&app, PASSWORD_HINTS_FILE, &*hints);
+    Ok(())
+}
+
+// The passphrase hint for `path` (default: the current database). After a
+// `wrong_passphrase` error, pass the `path` it carries.
+#[tauri::command]
+async fn get_password_hint(
+    state: State<'_, AppState>,
+    path: Option<String>,
+) -> Result<Option<String>, BrewError> {
+    let path = match path {
+        Some(path) => path,
+        None => path_string(&current_path(&state)?),
+    };
+    Ok(state.password_hints.lock().unwrap().get(&path).cloned())
+}
+
+// Get the current database path
+#[tauri::command]
+async fn get_current_db_path(state: State<'_, AppState>) -> Result<DbPathResponse, BrewError> {
+    let current_path = state.current_db_path.lock().unwrap();
+    Ok(DbPathResponse {
+        path: current_path.as_deref().map(path_string),
+        path_display: current_path.as_deref().map(path_display),
+    })
+}
+
+// Version, source ID and compile options of the bundled SQLite library
+#[tauri::command]
+async fn sqlite_version() -> Result<sqlite::SqliteBuildInfo, BrewError> {
+    Ok(sqlite::build_info()?)
+}
+
+// File size, modification time (Unix millis) and permissions of the current database
+#[tauri::command]
+async fn get_db_info(state: State<'_, AppState>) -> Result<DbInfo, BrewError> {
+    let path = current_path(&state)?;
+    let metadata = std::fs::metadata(&path)?;
+
+    Ok(DbInfo {
+        size_bytes: metadata.len(),
+        modified: metadata.modified().ok().and_then(unix_millis),
+        readonly: metadata.permissions().readonly(),
+        snapshot: sqlite::open_read_only(&path)
+            .and_then(|conn| export::snapshot_info(&conn))
+            .ok()
+            .flatten(),
+        path: Some(path_string(&path)),
+        path_display: path_display(&path),
+    })
+}
+
+// Whether `path` changed on disk since we last opened or saved it. A file
+// that has disappeared counts as changed.
+fn changed_externally(state: &AppState, path: &std::path::Path) -> Result<bool, BrewError> {
+    match storage::FileStamp::of(path) {
+        Ok(stamp) => Ok(*state.disk_stamp.lock().unwrap() != Some(stamp)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Whether the current file changed on disk since we last opened or saved it
+#[tauri::command]
+async fn check_external_changes(state: State<'_, AppState>) -> Result<bool, BrewError> {
+    let path = current_path(&state)?;
+    changed_externally(&state, &path)
+}
+
+// Run SQLite's integrity check against the current file without modifying it
+#[tauri::command]
+async fn verify_integrity(state: State<'_, AppState>) -> Result<sqlite::IntegrityReport, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    Ok(sqlite::integrity_check(&conn)?)
+}
+
+// Best-effort rescue for when `verify_integrity` fails: copy every row that
+// can still be read into a fresh database and save it with a dialog. The
+// damaged file is left untouched and can't be picked as the target. The warning says how much was recovered;
+// rows on unreadable pages may not be counted as lost at all.
+#[tauri::command]
+async fn recover_database(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let result = read_bounded(&state, &path, false)
+        .and_then(|raw| decode_database(raw, true, open_limit(&state, false)))
+        .and_then(|data| recover::recover(&data));
+    record_activity(&state, "recover", &path, &result);
+    let recover::Recovered { data, report } = result?;
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(format!("{}-recovered", stem))
+        .blocking_save_file();
+
+    let target = dialog_path(file_path)?;
+    let (target, _) = normalize_db_path(target, &config, None);
+    let len = data.len();
+    write_export(&state, &target, data).await?;
+    remember_directory(&app, &state, &target);
+
+    let mut summary = format!(
+        "Best-effort recovery: {} rows in {} tables recovered, at least {} rows lost",
+        report.rows_recovered,
+        report.tables.len(),
+        report.rows_lost
+    );
+    if !report.tables_lost.is_empty() {
+        summary.push_str(&format!("; tables lost: {}", report.tables_lost.join(", ")));
+    }
+    if !report.objects_skipped.is_empty() {
+        summary.push_str(&format!(
+            "; indexes, views or triggers not recreated: {}",
+            report.objects_skipped.join(", ")
+        ));
+    }
+    log::info!("recover {}: {}", path.display(), summary);
+
+    Ok(SaveResponse {
+        warning: Some(summary),
+        ..SaveResponse::saved(&target, Some(len as u64))
+    })
 }
 
-#[derive(Serialize, Deserialize)]
-struct DbPathResponse {
-    path: Option<String>,
+// Tables in the current file with their columns and row counts
+#[tauri::command]
+async fn get_schema(state: State<'_, AppState>) -> Result<Vec<sqlite::TableInfo>, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    Ok(sqlite::schema(&conn)?)
 }
 
-#[derive(Serialize, Deserialize)]
-struct SaveResponse {
-    success: bool,
-    path: Option<String>,
-    error: Option<String>,
+// Compact the current file with VACUUM, through the live connection if one
+// is open. The file on disk changes, so the frontend should reload it.
+#[tauri::command]
+async fn vacuum_database(state: State<'_, AppState>) -> Result<VacuumReport, BrewError> {
+    let path = current_path(&state)?;
+    let path_ref = path.as_path();
+    ensure_writable(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+
+    let size_before = std::fs::metadata(path_ref)?.len();
+    // VACUUM builds a full copy and journals it, so it can need up to twice
+    // the current size while it runs
+    ensure_free_space(path_ref, (size_before as usize).saturating_mul(2))?;
+
+    let result = {
+        let live = state.live_connection.lock().unwrap();
+        match live.as_ref() {
+            Some(conn) => sqlite::vacuum(conn),
+            None => sqlite::open_read_write(path_ref).and_then(|conn| sqlite::vacuum(&conn)),
+        }
+    }
+    .map_err(BrewError::from);
+    record_activity(&state, "vacuum", path_ref, &result);
+    result?;
+
+    let size_after = std::fs::metadata(path_ref)?.len();
+    record_disk_stamp(&state, path_ref);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(path_ref)?);
+
+    Ok(VacuumReport {
+        size_before,
+        size_after,
+        bytes_reclaimed: size_before.saturating_sub(size_after),
+    })
 }
 
-// Save database to a new location (opens save dialog)
+// Run `task` against the current file through the live connection, or a
+// writable one opened for it. The file changes on disk, so the frontend's
+// copy is marked dirty.
+fn maintain_current(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    operation: &str,
+    task: fn(&rusqlite::Connection) -> rusqlite::Result<()>,
+) -> Result<(), BrewError> {
+    let path = current_path(state)?;
+    ensure_writable(state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+
+    let result = {
+        let live = state.live_connection.lock().unwrap();
+        match live.as_ref() {
+            Some(conn) => task(conn),
+            None => sqlite::open_read_write(&path).and_then(|conn| task(&conn)),
+        }
+    }
+    .map_err(BrewError::from);
+    record_activity(state, operation, &path, &result);
+    result?;
+
+    record_disk_stamp(state, &path);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(&path)?);
+    set_dirty(app, state, true);
+    Ok(())
+}
+
+// Rebuild every index in the current file
 #[tauri::command]
-async fn save_database_as(
-    app: tauri::AppHandle,
-    state: State<'_, AppState>,
-    data: Vec<u8>,
-) -> Result<SaveResponse, String> {
-    use tauri_plugin_dialog::{DialogExt, FilePath};
-
-    let file_path = app
-        .dialog()
-        .file()
-        .add_filter("SQLite Database", &["db"])
-        .set_file_name("brewcode.db")
-        .blocking_save_file();
+async fn reindex_database(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), BrewError> {
+    maintain_current(&app, &state, "reindex", sqlite::reindex)
+}
 
-    match file_path {
-        Some(FilePath::Path(path)) => {
-            match std::fs::write(&path, data) {
-                Ok(_) => {
-                    let path_str = path.to_string_lossy().to_string();
-                    let mut current_path = state.current_db_path.lock().unwrap();
-                    *current_path = Some(path_str.clone());
-                    
-                    Ok(SaveResponse {
-                        success: true,
-                        path: Some(path_str),
-                        error: None,
-                    })
-                }
-                Err(e) => Ok(SaveResponse {
-                    success: false,
-                    path: None,
-                    error: Some(format!("Failed to write file: {}", e)),
-                }),
-            }
+// Refresh the current file's query-planner statistics
+#[tauri::command]
+async fn analyze_database(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), BrewError> {
+    maintain_current(&app, &state, "analyze", sqlite::analyze)
+}
+
+// Upgrade the current file to the latest schema, backing it up first. The
+// file on disk changes, so the frontend should reload it afterwards.
+#[tauri::command]
+async fn migrate_database(state: State<'_, AppState>) -> Result<migrations::MigrationReport, BrewError> {
+    let path = current_path(&state)?;
+    let path_ref = path.as_path();
+    ensure_writable(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+
+    let header = storage::read_prefix(path_ref, SQLITE_HEADER_BYTES)?;
+    let version = migrations::header_version(&header).unwrap_or(0);
+    migrations::ensure_supported(version)?;
+    if version == migrations::LATEST_VERSION {
+        return Ok(migrations::MigrationReport {
+            from: version,
+            to: version,
+        });
+    }
+
+    // Always keep at least one backup of the pre-migration file
+    let backup_count = state.settings.lock().unwrap().backup_count.max(1);
+    storage::rotate_backups(path_ref, backup_count)?;
+
+    let result = {
+        let mut live = state.live_connection.lock().unwrap();
+        match live.as_mut() {
+            Some(conn) => migrations::migrate(conn),
+            None => sqlite::open_read_write(path_ref)
+                .map_err(BrewError::from)
+                .and_then(|mut conn| migrations::migrate(&mut conn)),
         }
-        _ => Ok(SaveResponse {
-            success: false,
-            path: None,
-            error: Some("Save cancelled".to_string()),
-        }),
+    };
+    record_activity(&state, "migrate", path_ref, &result);
+    let report = result?;
+    log::info!("migrate {}: schema {} -> {}", path.display(), report.from, report.to);
+
+    record_disk_stamp(&state, path_ref);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(path_ref)?);
+    Ok(report)
+}
+
+// Open a read-write rusqlite connection to the current file, kept in AppState
+// until `save_live` closes it. The reference database, if set, is attached.
+#[tauri::command]
+async fn open_live_connection(state: State<'_, AppState>) -> Result<(), BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_write(&path)?;
+    attach_reference_to(&state, &conn)?;
+    *state.live_connection.lock().unwrap() = Some(conn);
+    Ok(())
+}
+
+// Attach the reference database to `conn` as `ref`, read-only. Does nothing
+// if none is set; fails if its file has gone away since it was chosen.
+fn attach_reference_to(state: &AppState, conn: &rusqlite::Connection) -> Result<(), BrewError> {
+    let Some(path) = state.reference_db.lock().unwrap().clone() else {
+        return Ok(());
+    };
+    if !path.is_file() {
+        return Err(BrewError::ReferenceUnavailable(format!(
+            "{} no longer exists",
+            path.display()
+        )));
     }
+    Ok(sqlite::attach_read_only(conn, &path, REFERENCE_ALIAS)?)
 }
 
-// Save database to the current location
+// Use `path` as a shared read-only reference, e.g. an ingredient catalog,
+// that `query_database` and the live connection can read as `ref.<table>`.
+// Replaces any previous reference.
 #[tauri::command]
-async fn save_database(
+async fn attach_reference(state: State<'_, AppState>, path: String) -> Result<(), BrewError> {
+    let path = storage::path_from_key(&path);
+    if !has_sqlite_header_on_disk(&path) {
+        return Err(BrewError::InvalidDatabase(format!(
+            "{} is not a SQLite database",
+            path.display()
+        )));
+    }
+    clear_reference(&state)?;
+    *state.reference_db.lock().unwrap() = Some(path);
+
+    let live = state.live_connection.lock().unwrap();
+    match live.as_ref() {
+        Some(conn) => attach_reference_to(&state, conn),
+        None => Ok(()),
+    }
+}
+
+// Stop using the reference database
+#[tauri::command]
+async fn detach_reference(state: State<'_, AppState>) -> Result<(), BrewError> {
+    clear_reference(&state)
+}
+
+// Forget the reference and detach it from the live connection
+fn clear_reference(state: &AppState) -> Result<(), BrewError> {
+    if state.reference_db.lock().unwrap().take().is_none() {
+        return Ok(());
+    }
+    let live = state.live_connection.lock().unwrap();
+    if let Some(conn) = live.as_ref() {
+        conn.execute_batch(&format!("DETACH DATABASE {}", REFERENCE_ALIAS))?;
+    }
+    Ok(())
+}
+
+// Run one read-only statement against the current file and return up to
+// `MAX_QUERY_ROWS` rows. With `with_reference`, the reference database is
+// attached as `ref` for cross-database queries.
+#[tauri::command]
+async fn query_database(
     state: State<'_, AppState>,
-    data: Vec<u8>,
-) -> Result<SaveResponse, String> {
-    let current_path = state.current_db_path.lock().unwrap();
-    
-    match current_path.as_ref() {
-        Some(path) => {
-            match std::fs::write(path, data) {
-                Ok(_) => Ok(SaveResponse {
-                    success: true,
-                    path: Some(path.clone()),
-                    error: None,
-                }),
-                Err(e) => Ok(SaveResponse {
-                    success: false,
-                    path: None,
-                    error: Some(format!("Failed to write file: {}", e)),
-                }),
-            }
+    sql: String,
+    with_reference: Option<bool>,
+) -> Result<sqlite::QueryResult, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    if with_reference.unwrap_or(false) {
+        if state.reference_db.lock().unwrap().is_none() {
+            return Err(BrewError::ReferenceUnavailable(
+                "no reference database is attached".to_string(),
+            ));
         }
-        None => Ok(SaveResponse {
-            success: false,
-            path: None,
-            error: Some("No database path set. Use 'Save As' first.".to_string()),
-        }),
+        attach_reference_to(&state, &conn)?;
     }
+    sqlite::query(&conn, &sql, &[], MAX_QUERY_ROWS)
 }
 
-// Open existing database file
+// A page of `table` for a virtualized grid, at most `MAX_QUERY_ROWS` rows,
+// read from the current file without writing to it
 #[tauri::command]
-async fn open_database(
+async fn query_table_page(
+    state: State<'_, AppState>,
+    table: String,
+    offset: u64,
+    limit: u32,
+    order_by: Option<String>,
+) -> Result<sqlite::TablePage, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    let limit = limit.min(MAX_QUERY_ROWS as u32);
+    sqlite::table_page(&conn, &table, offset, limit, order_by.as_deref())
+}
+
+// Rough size of `table` in the current file, so the UI can warn before
+// loading one too big for the grid
+#[tauri::command]
+async fn estimate_table_size(
+    state: State<'_, AppState>,
+    table: String,
+) -> Result<sqlite::SizeEstimate, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    sqlite::estimate_table_size(&conn, &table)
+}
+
+// Write half of the query console, off unless the `allow_writes` setting is
+// on: run `statements` in one transaction against the current file, through
+// the live connection if one is open. Any failure rolls back every statement.
+#[tauri::command]
+async fn run_write(
     app: tauri::AppHandle,
     state: State<'_, AppState>,
-) -> Result<Vec<u8>, String> {
-    use tauri_plugin_dialog::{DialogExt, FilePath};
+    statements: Vec<String>,
+) -> Result<sqlite::WriteResult, BrewError> {
+    if !state.settings.lock().unwrap().allow_writes {
+        return Err(BrewError::PermissionDenied(
+            "writing from the query console is turned off in settings".to_string(),
+        ));
+    }
+    let path = current_path(&state)?;
+    ensure_writable(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
 
-    let file_path = app
-        .dialog()
-        .file()
-        .add_filter("SQLite Database", &["db"])
-        .blocking_pick_file();
+    let result = {
+        let mut live = state.live_connection.lock().unwrap();
+        match live.as_mut() {
+            Some(conn) => sqlite::execute_all(conn, &statements),
+            None => sqlite::open_read_write(&path)
+                .map_err(BrewError::from)
+                .and_then(|mut conn| sqlite::execute_all(&mut conn, &statements)),
+        }
+    };
+    record_activity(&state, "run write", &path, &result);
+    let written = result?;
 
-    match file_path {
-        Some(FilePath::Path(path)) => {
-            match std::fs::read(&path) {
-                Ok(data) => {
-                    let path_str = path.to_string_lossy().to_string();
-                    let mut current_path = state.current_db_path.lock().unwrap();
-                    *current_path = Some(path_str);
-                    
-                    Ok(data)
-                }
-                Err(e) => Err(format!("Failed to read file: {}", e)),
-            }
+    // Our own write shouldn't show up as an external change
+    record_disk_stamp(&state, &path);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(&path)?);
+    set_dirty(&app, &state, true);
+    Ok(written)
+}
+
+// Ad-hoc query console: run one statement that doesn't write, with `params`
+// bound to its placeholders, against the current file and return at most
+// `MAX_QUERY_ROWS` rows. The file is opened read-only with `query_only` on, so
+// nothing can write through here; a statement still running after
+// `QUERY_TIMEOUT_SECS` is interrupted.
+#[tauri::command]
+async fn run_query(
+    state: State<'_, AppState>,
+    sql: String,
+    params: Vec<serde_json::Value>,
+) -> Result<sqlite::QueryResult, BrewError> {
+    let path = current_path(&state)?;
+    let params = params
+        .iter()
+        .map(sqlite::param_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let conn = sqlite::open_read_only(&path)?;
+    conn.execute_batch("PRAGMA query_only = ON")?;
+    let interrupt = conn.get_interrupt_handle();
+
+    let task = tauri::async_runtime::spawn_blocking(move || {
+        sqlite::query(&conn, &sql, &params, MAX_QUERY_ROWS)
+    });
+    match tokio::time::timeout(std::time::Duration::from_secs(QUERY_TIMEOUT_SECS), task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(BrewError::Io(std::io::Error::other(e.to_string()))),
+        Err(_) => {
+            interrupt.interrupt();
+            Err(BrewError::QueryTimeout {
+                seconds: QUERY_TIMEOUT_SECS,
+            })
         }
-        _ => Err("Open cancelled".to_string()),
     }
 }
 
-// Export database copy to a different location (doesn't change current path)
+// Page and free-page counts of the current file, to tell whether a vacuum is
+// worth running
 #[tauri::command]
-async fn export_database(
+async fn get_storage_stats(state: State<'_, AppState>) -> Result<sqlite::StorageStats, BrewError> {
+    let path = current_path(&state)?;
+    let conn = sqlite::open_read_only(&path)?;
+    Ok(sqlite::storage_stats(&conn)?)
+}
+
+// SQLite settings of the live connection
+#[tauri::command]
+async fn get_pragmas(state: State<'_, AppState>) -> Result<sqlite::PragmaSettings, BrewError> {
+    let live = state.live_connection.lock().unwrap();
+    let conn = live.as_ref().ok_or(BrewError::NoLiveConnection)?;
+    Ok(sqlite::pragmas(conn)?)
+}
+
+// Change one whitelisted SQLite setting on the live connection and return
+// the value SQLite reports afterwards. Per-connection settings such as
+// `synchronous` last until the connection closes.
+#[tauri::command]
+async fn set_pragma(
+    state: State<'_, AppState>,
+    name: String,
+    value: String,
+) -> Result<sqlite::PragmaValue, BrewError> {
+    ensure_writable(&state)?;
+    let live = state.live_connection.lock().unwrap();
+    let conn = live.as_ref().ok_or(BrewError::NoLiveConnection)?;
+    sqlite::set_pragma(conn, &name, &value)
+}
+
+// Whether the live connection enforces foreign keys
+#[tauri::command]
+async fn get_foreign_keys_enabled(state: State<'_, AppState>) -> Result<bool, BrewError> {
+    current_path(&state)?;
+    let live = state.live_connection.lock().unwrap();
+    let conn = live.as_ref().ok_or(BrewError::NoLiveConnection)?;
+    Ok(sqlite::pragmas(conn)?.foreign_keys)
+}
+
+// Turn foreign-key enforcement on the live connection on or off. Returns the
+// resulting state, which doesn't change while a transaction is open.
+#[tauri::command]
+async fn set_foreign_keys_enabled(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<bool, BrewError> {
+    current_path(&state)?;
+    let live = state.live_connection.lock().unwrap();
+    let conn = live.as_ref().ok_or(BrewError::NoLiveConnection)?;
+    Ok(sqlite::set_foreign_keys(conn, enabled)?)
+}
+
+// Rows of the current file that break a foreign key, checked through the live
+// connection when one is open so uncommitted rows are included
+#[tauri::command]
+async fn check_foreign_keys(
+    state: State<'_, AppState>,
+) -> Result<Vec<sqlite::FkViolation>, BrewError> {
+    let path = current_path(&state)?;
+    let live = state.live_connection.lock().unwrap();
+    let violations = match live.as_ref() {
+        Some(conn) => sqlite::foreign_key_violations(conn),
+        None => sqlite::open_read_only(&path)
+            .and_then(|conn| sqlite::foreign_key_violations(&conn)),
+    };
+    Ok(violations?)
+}
+
+// Finish a save made through the live connection. Requires a connection from
+// `open_live_connection`; all of its writes must already be committed. The
+// WAL is checkpointed and truncated and the connection closed, so the main
+// file on disk holds every change and can be copied or shared as-is. Until
+// then `save_database` refuses to run, since its bytes would be stale next to
+// a live WAL.
+#[tauri::command]
+async fn save_live(
     app: tauri::AppHandle,
-    data: Vec<u8>,
-) -> Result<SaveResponse, String> {
-    use tauri_plugin_dialog::{DialogExt, FilePath};
-
-    let file_path = app
-        .dialog()
-        .file()
-        .add_filter("SQLite Database", &["db"])
-        .set_file_name("brewcode.db")
-        .blocking_save_file();
+    state: State<'_, AppState>,
+) -> Result<SaveResponse, BrewError> {
+    let path = current_path(&state)?;
+    let mut live = state.live_connection.lock().unwrap();
+    let conn = live.take().ok_or(BrewError::NoLiveConnection)?;
 
-    match file_path {
-        Some(FilePath::Path(path)) => {
-            match std::fs::write(&path, data) {
-                Ok(_) => {
-                    let path_str = path.to_string_lossy().to_string();
-                    Ok(SaveResponse {
-                        success: true,
-                        path: Some(path_str),
-                        error: None,
-                    })
-                }
-                Err(e) => Ok(SaveResponse {
-                    success: false,
-                    path: None,
-                    error: Some(format!("Failed to write file: {}", e)),
-                }),
-            }
+    match sqlite::checkpoint_truncate(&conn) {
+        Ok(true) => {}
+        Ok(false) => {
+            *live = Some(conn);
+            return Err(BrewError::CheckpointIncomplete);
+        }
+        Err(e) => {
+            *live = Some(conn);
+            return Err(e.into());
         }
-        _ => Ok(SaveResponse {
-            success: false,
-            path: None,
-            error: Some("Export cancelled".to_string()),
-        }),
     }
+    if let Err((conn, e)) = conn.close() {
+        *live = Some(conn);
+        return Err(e.into());
+    }
+
+    let path_ref = path.as_path();
+    record_disk_stamp(&state, path_ref);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(path_ref)?);
+    set_dirty(&app, &state, false);
+    clear_journal(&app);
+
+    Ok(SaveResponse::saved(&path, None))
 }
 
-// Get the current database path
+// Result of `finalize_for_sharing`
+#[derive(Serialize)]
+struct SharingResponse {
+    #[serde(flatten)]
+    save: SaveResponse,
+    // Whether `-wal` or `-shm` files sat next to the current file
+    had_sidecars: bool,
+    // Size of the WAL folded into the main file
+    wal_bytes: u64,
+}
+
+// Save a single self-contained copy of the current file for sharing: its WAL
+// is checkpointed into it, and the copy is marked as not using WAL so it
+// never expects `-wal`/`-shm` files. The working file keeps its journal mode.
 #[tauri::command]
-async fn get_current_db_path(state: State<'_, AppState>) -> Result<DbPathResponse, String> {
-    let current_path = state.current_db_path.lock().unwrap();
-    Ok(DbPathResponse {
-        path: current_path.clone(),
+async fn finalize_for_sharing(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<SharingResponse, BrewError> {
+    let path = current_path(&state)?;
+    if *state.encrypted.lock().unwrap() {
+        return Err(BrewError::PassphraseRequired);
+    }
+    let sidecar = |suffix: &str| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let wal_bytes = std::fs::metadata(sidecar("-wal")).map_or(0, |m| m.len());
+    let had_sidecars = sidecar("-wal").exists() || sidecar("-shm").exists();
+
+    let mut data = {
+        let live = state.live_connection.lock().unwrap();
+        let opened;
+        let conn = match live.as_ref() {
+            Some(conn) => conn,
+            None => {
+                opened = sqlite::open_read_write(&path)?;
+                &opened
+            }
+        };
+        if !sqlite::checkpoint_truncate(conn)? {
+            return Err(BrewError::CheckpointIncomplete);
+        }
+        conn.serialize(rusqlite::MAIN_DB)?.to_vec()
+    };
+    sqlite::set_rollback_journal(&mut data);
+    // The checkpoint rewrote the working file, though not what it holds
+    record_disk_stamp(&state, &path);
+    *state.saved_checksum.lock().unwrap() = Some(storage::sha256_file(&path)?);
+
+    let config = state.dialog_config.lock().unwrap().clone();
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_path = file_dialog(&app, &state, &config)
+        .set_file_name(format!("{}-shared", stem))
+        .blocking_save_file();
+    let target = dialog_path(file_path)?;
+    let (target, warning) = normalize_db_path(target, &config, None);
+    let len = data.len();
+    write_export(&state, &target, data).await?;
+    remember_directory(&app, &state, &target);
+
+    Ok(SharingResponse {
+        save: SaveResponse {
+            warning,
+            ..SaveResponse::saved(&target, Some(len as u64))
+        },
+        had_sidecars,
+        wal_bytes,
     })
 }
 
+// Emit `autosave-tick` every `secs` seconds while a database path is set; the
+// frontend answers each tick by sending its bytes to `save_database`
+async fn autosave_loop(app: tauri::AppHandle, secs: u32) {
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(secs.into()));
+    // The first tick completes immediately; skip it so we don't save on enable
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let state = app.state::<AppState>();
+        if state.current_db_path.lock().unwrap().is_none() {
+            continue;
+        }
+        if let Err(e) = app.emit("autosave-tick", ()) {
+            log::warn!("Failed to emit autosave-tick: {}", e);
+        }
+    }
+}
+
+// Start autosave with the given interval, or stop it with `None`/0. Any
+// previously running timer is cancelled first.
+#[tauri::command]
+async fn configure_autosave(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    interval_secs: Option<u32>,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        autosave_interval: Some(interval_secs.unwrap_or(0)),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Stop the autosave timer and start a new one if `interval_secs` is set
+fn restart_autosave(app: &tauri::AppHandle, state: &AppState, interval_secs: Option<u32>) {
+    let mut task = state.autosave_task.lock().unwrap();
+    if let Some(previous) = task.take() {
+        previous.abort();
+    }
+    if let Some(secs) = interval_secs {
+        *task = Some(tauri::async_runtime::spawn(autosave_loop(app.clone(), secs)));
+    }
+}
+
+// Current autosave interval in seconds, or `None` when autosave is off
+#[tauri::command]
+async fn get_autosave_interval(state: State<'_, AppState>) -> Result<Option<u32>, BrewError> {
+    Ok(state.settings.lock().unwrap().autosave_interval)
+}
+
+// Snapshots: the top of the undo stack is the state the frontend last pushed,
+// i.e. what's on screen. Undo moves it to the redo stack and returns the one
+// below it. Lock order is always undo then redo.
+
+// Drop the oldest snapshots beyond `depth`
+fn trim_snapshots(stack: &mut Vec<Vec<u8>>, depth: usize) {
+    if stack.len() > depth {
+        let excess = stack.len() - depth;
+        stack.drain(..excess);
+    }
+}
+
+// Record the database as it is after an edit; clears anything left to redo
+#[tauri::command]
+async fn push_snapshot(state: State<'_, AppState>, data: Vec<u8>) -> Result<(), BrewError> {
+    let depth = state.settings.lock().unwrap().snapshot_depth;
+    let mut undo = state.undo_stack.lock().unwrap();
+    undo.push(data);
+    trim_snapshots(&mut undo, depth);
+    state.redo_stack.lock().unwrap().clear();
+    Ok(())
+}
+
+// Step back to the previous snapshot and return its bytes
+#[tauri::command]
+async fn undo(state: State<'_, AppState>) -> Result<Vec<u8>, BrewError> {
+    let mut undo = state.undo_stack.lock().unwrap();
+    if undo.len() < 2 {
+        return Err(BrewError::NothingToUndo);
+    }
+
+    let current = undo.pop().unwrap();
+    state.redo_stack.lock().unwrap().push(current);
+    Ok(undo.last().unwrap().clone())
+}
+
+// Re-apply the most recently undone snapshot and return its bytes
+#[tauri::command]
+async fn redo(state: State<'_, AppState>) -> Result<Vec<u8>, BrewError> {
+    let depth = state.settings.lock().unwrap().snapshot_depth;
+    let mut undo = state.undo_stack.lock().unwrap();
+    let next = state
+        .redo_stack
+        .lock()
+        .unwrap()
+        .pop()
+        .ok_or(BrewError::NothingToRedo)?;
+
+    undo.push(next.clone());
+    trim_snapshots(&mut undo, depth);
+    Ok(next)
+}
+
+#[tauri::command]
+async fn clear_snapshots(state: State<'_, AppState>) -> Result<(), BrewError> {
+    state.undo_stack.lock().unwrap().clear();
+    state.redo_stack.lock().unwrap().clear();
+    Ok(())
+}
+
+// Total bytes held by both snapshot stacks
+#[tauri::command]
+async fn snapshot_memory_bytes(state: State<'_, AppState>) -> Result<u64, BrewError> {
+    let undo = state.undo_stack.lock().unwrap();
+    let redo = state.redo_stack.lock().unwrap();
+    Ok(undo.iter().chain(redo.iter()).map(|s| s.len() as u64).sum())
+}
+
+// Maximum number of undo snapshots kept; the oldest are dropped beyond it
+#[tauri::command]
+async fn configure_snapshots(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    depth: usize,
+) -> Result<(), BrewError> {
+    let partial = settings::PartialSettings {
+        snapshot_depth: Some(depth),
+        ..Default::default()
+    };
+    update_settings_with(&app, &state, partial);
+    Ok(())
+}
+
+// Location of the log file, for attaching to bug reports
+#[tauri::command]
+async fn get_log_path(app: tauri::AppHandle) -> Result<Option<String>, BrewError> {
+    Ok(app
+        .path()
+        .app_log_dir()
+        .ok()
+        .map(|dir| path_string(&dir.join(format!("{}.log", LOG_FILE_NAME)))))
+}
+
+// Whether `path` can be saved to; call between the dialog and sending bytes
+#[tauri::command]
+async fn can_write_to(path: String) -> Result<bool, BrewError> {
+    Ok(storage::can_write_to(&storage::path_from_key(&path)))
+}
+
+// Show the current database in Finder / Explorer / the Linux file manager,
+// with the file selected where the platform supports it
+#[tauri::command]
+async fn reveal_in_file_manager(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), BrewError> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let path = current_path(&state)?;
+    app.opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| BrewError::FileManager(e.to_string()))
+}
+
 // Check if a database file exists at the stored path
 #[tauri::command]
-async fn check_db_exists(state: State<'_, AppState>) -> Result<bool, String> {
+async fn check_db_exists(state: State<'_, AppState>) -> Result<bool, BrewError> {
     let current_path = state.current_db_path.lock().unwrap();
     
     match current_path.as_ref() {
@@ -187,17 +4081,153 @@ async fn check_db_exists(state: State<'_, AppState>) -> Result<bool, String> {
     }
 }
 
+// The database path in a launch command line: the last argument that isn't
+// a flag (macOS adds `-psn_...` when launched from Finder). Relative paths
+// are resolved against `cwd`. A table to show first can follow as
+// `--table <name>`, `--table=<name>` or a `#table=<name>` suffix on the path.
+fn launch_path_from_args<I: IntoIterator<Item = String>>(
+    args: I,
+    cwd: &std::path::Path,
+) -> Option<(PathBuf, Option<String>)> {
+    let mut args = args.into_iter().skip(1);
+    let mut path = None;
+    let mut table = None;
+    while let Some(arg) = args.next() {
+        if arg == "--table" {
+            table = args.next();
+        } else if let Some(name) = arg.strip_prefix("--table=") {
+            table = Some(name.to_string());
+        } else if !arg.starts_with('-') {
+            path = Some(arg);
+        }
+    }
+
+    let mut path = path?;
+    if let Some(start) = path.rfind("#table=") {
+        let name = path.split_off(start)["#table=".len()..].to_string();
+        table = table.or(Some(name));
+    }
+    Some((cwd.join(path), table.filter(|name| !name.is_empty())))
+}
+
+// Offer a file we were launched with to the frontend: stored for
+// `take_launch_path` and announced with `open-on-launch`. Files that can't be
+// read are reported with `open-on-launch-failed` instead. A `table` the file
+// doesn't have is dropped, so the file still opens.
+fn offer_launch_path(app: &tauri::AppHandle, path: PathBuf, table: Option<String>) {
+    let path_str = path_string(&path);
+
+    if let Err(e) = std::fs::File::open(&path) {
+        let error = BrewError::from(e);
+        let failure = LaunchFailure {
+            path: path_str,
+            kind: error.kind(),
+            message: error.to_string(),
+        };
+        if let Err(e) = app.emit("open-on-launch-failed", failure) {
+            log::warn!("Failed to emit open-on-launch-failed: {}", e);
+        }
+        return;
+    }
+
+    let table = table.filter(|name| {
+        let found = sqlite::open_read_only(&path)
+            .and_then(|conn| sqlite::has_table_or_view(&conn, name))
+            .unwrap_or(false);
+        if !found {
+            log::info!("Ignoring launch table {}: not found in {}", name, path.display());
+        }
+        found
+    });
+    let launch = LaunchOpen {
+        path: path_str,
+        table,
+    };
+    *app.state::<AppState>().launch_path.lock().unwrap() = Some(launch.clone());
+    if let Err(e) = app.emit("open-on-launch", launch) {
+        log::warn!("Failed to emit open-on-launch: {}", e);
+    }
+}
+
+// The file brewcode was launched with, if the frontend hasn't taken it yet.
+// Covers launches where `open-on-launch` fired before anything listened.
+#[tauri::command]
+async fn take_launch_path(state: State<'_, AppState>) -> Result<Option<LaunchOpen>, BrewError> {
+    Ok(state.launch_path.lock().unwrap().take())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-  tauri::Builder::default()
+  let builder = tauri::Builder::default();
+
+  // A second launch hands its file to this instance and exits. The plugin
+  // has to be registered before any other.
+  #[cfg(desktop)]
+  let builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+    if let Some((path, table)) = launch_path_from_args(argv, std::path::Path::new(&cwd)) {
+      offer_launch_path(app, path, table);
+    }
+    if let Some(window) = app.webview_windows().values().next() {
+      let _ = window.unminimize();
+      let _ = window.set_focus();
+    }
+  }));
+
+  builder
     .plugin(tauri_plugin_dialog::init())
+    .plugin(tauri_plugin_opener::init())
     .setup(|app| {
+      use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+
+      // Always log to a file so users can attach it to bug reports
+      let mut logger = tauri_plugin_log::Builder::default()
+        .level(log::LevelFilter::Info)
+        .clear_targets()
+        .target(Target::new(TargetKind::LogDir {
+          file_name: Some(LOG_FILE_NAME.to_string()),
+        }))
+        .max_file_size(LOG_MAX_BYTES)
+        .rotation_strategy(RotationStrategy::KeepSome(3));
       if cfg!(debug_assertions) {
-        app.handle().plugin(
-          tauri_plugin_log::Builder::default()
-            .level(log::LevelFilter::Info)
-            .build(),
-        )?;
+        logger = logger.target(Target::new(TargetKind::Stdout));
+      }
+      app.handle().plugin(logger.build())?;
+
+      let state = app.state::<AppState>();
+      state.config.init(app.path().app_config_dir());
+      report_config_unavailable(app.handle());
+      *state.recent_files.lock().unwrap() = load_recent_files(app.handle());
+      *state.last_directory.lock().unwrap() =
+        load_config::<Option<PathBuf>>(app.handle(), LAST_DIRECTORY_FILE).flatten();
+      *state.view_states.lock().unwrap() = load_view_states(app.handle());
+      *state.password_hints.lock().unwrap() = load_password_hints(app.handle());
+      *state.forbidden_dirs.lock().unwrap() = forbidden_dirs(app.handle());
+      if let Some(recovery) = load_recovery(app.handle()) {
+        *state.recovery.lock().unwrap() = Some(recovery.clone());
+        emit_logged(app.handle(), "recover-available", recovery);
+      }
+
+      let settings = load_settings(app.handle());
+      restart_autosave(app.handle(), &state, settings.autosave_interval);
+      let reopen_last = settings.reopen_last_on_launch;
+      *state.settings.lock().unwrap() = settings;
+
+      // A file passed on the command line wins over the remembered one
+      let launch_path = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| launch_path_from_args(std::env::args(), &cwd));
+      if let Some((path, table)) = launch_path {
+        offer_launch_path(app.handle(), path, table);
+      } else if reopen_last {
+        let last = state
+          .recent_files
+          .lock()
+          .unwrap()
+          .first()
+          .map(|entry| storage::path_from_key(&entry.path));
+        if let Some(path) = last.filter(|path| path.is_file()) {
+          offer_launch_path(app.handle(), path, None);
+        }
       }
       Ok(())
     })
@@ -205,11 +4235,229 @@ pub fn run() {
     .invoke_handler(tauri::generate_handler![
       save_database_as,
       save_database,
+      save_database_resolving,
+      save_database_to,
+      save_database_encrypted,
       open_database,
+      open_database_at,
+      open_database_streamed,
+      open_database_encrypted,
+      inspect_database,
+      open_database_from_url,
+      diff_against_disk,
+      compare_files,
+      cancel_open,
+      cancel_export,
       export_database,
+      export_subset,
+      export_readonly_snapshot,
+      export_sql,
+      export_sql_to_file,
+      export_table_json,
+      export_secure_bundle,
+      open_secure_bundle,
+      save_project,
+      open_project,
+      export_all_csv_zip,
+      export_table_ndjson,
+      save_temp,
+      cleanup_temp_files,
+      list_stray_files,
+      prune_stray_files,
+      import_csv,
+      merge_database,
+      rename_database,
+      configure_import,
+      duplicate_database,
+      new_database,
+      close_database,
+      open_into_slot,
+      save_slot,
+      close_slot,
+      list_slots,
+      list_templates,
+      new_from_template,
+      export_base64,
+      import_base64,
+      delete_database,
+      is_locked_elsewhere,
+      force_unlock,
+      get_settings,
+      update_settings,
+      reset_settings,
+      configure_dialogs,
+      restore_backup,
+      verify_backup,
+      configure_backups,
+      configure_save_timeout,
+      configure_temp_strategy,
+      configure_reopen_last,
+      configure_durability,
+      configure_safe_mode,
+      mark_dirty,
+      configure_journal,
+      take_recovery,
+      discard_recovery,
+      is_dirty,
+      compute_checksum,
+      is_read_only,
+      get_recent_files,
+      recent_files_detailed,
+      remove_recent_file,
+      clear_recent_files,
+      save_view_state,
+      load_view_state,
+      set_password_hint,
+      get_password_hint,
       get_current_db_path,
+      get_db_info,
+      sqlite_version,
+      check_external_changes,
+      verify_integrity,
+      recover_database,
+      get_schema,
+      vacuum_database,
+      reindex_database,
+      analyze_database,
+      migrate_database,
+      export_for_version,
+      open_live_connection,
+      attach_reference,
+      detach_reference,
+      query_database,
+      run_query,
+      query_table_page,
+      estimate_table_size,
+      run_write,
+      get_pragmas,
+      get_storage_stats,
+      set_pragma,
+      get_foreign_keys_enabled,
+      set_foreign_keys_enabled,
+      check_foreign_keys,
+      save_live,
+      finalize_for_sharing,
+      configure_autosave,
+      get_autosave_interval,
+      push_snapshot,
+      undo,
+      redo,
+      clear_snapshots,
+      snapshot_memory_bytes,
+      configure_snapshots,
       check_db_exists,
+      reveal_in_file_manager,
+      can_write_to,
+      get_log_path,
+      get_activity_log,
+      clear_activity_log,
+      last_error,
+      take_launch_path,
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app, event| {
+      if let tauri::RunEvent::Exit = event {
+        remove_temp_files(&app.state::<AppState>());
+      }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file of `contents` in a fresh folder from `storage::tests::scratch_dir`
+    fn scratch_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = storage::tests::scratch_dir(name).join("brewcode.db");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_only_file_refuses_saves() {
+        let path = scratch_file("read-only", b"original");
+        let app = tauri::test::mock_app();
+        app.manage(AppState::default());
+        let state = app.state::<AppState>();
+        // Opened writable, then made read-only behind our back
+        *state.current_db_path.lock().unwrap() = Some(path.clone());
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+
+        let checksum = storage::sha256_hex(b"changed");
+        let saved = tauri::async_runtime::block_on(overwrite_current(
+            app.handle(),
+            &state,
+            &path,
+            b"changed".to_vec(),
+            checksum,
+        ));
+        assert!(matches!(saved, Err(BrewError::ReadOnly)));
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unsafe_location_override() {
+        let path = scratch_file("unsafe-location", b"original");
+        let state = AppState::default();
+        *state.forbidden_dirs.lock().unwrap() = vec![path.parent().unwrap().to_path_buf()];
+
+        assert!(matches!(
+            ensure_safe_location(&state, &path),
+            Err(BrewError::ForbiddenLocation(_))
+        ));
+        state.settings.lock().unwrap().allow_unsafe_location = true;
+        assert!(ensure_safe_location(&state, &path).is_ok());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn exports_refuse_the_current_file() {
+        let path = scratch_file("export-current", b"original");
+        let state = AppState::default();
+        *state.current_db_path.lock().unwrap() = Some(path.clone());
+
+        let written = tauri::async_runtime::block_on(write_export(&state, &path, b"copy".to_vec()));
+        assert!(matches!(written, Err(BrewError::TargetIsCurrent)));
+        #[cfg(unix)]
+        {
+            let link = path.with_file_name("link.db");
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            let written =
+                tauri::async_runtime::block_on(write_export(&state, &link, b"copy".to_vec()));
+            assert!(matches!(written, Err(BrewError::TargetIsCurrent)));
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn sqlite_header_is_accepted() {
+        let mut data = storage::SQLITE_MAGIC.to_vec();
+        data.extend_from_slice(&[0; 84]);
+        assert!(validate_database(&data, false).is_ok());
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00];
+        assert!(matches!(
+            validate_database(&jpeg, false),
+            Err(BrewError::InvalidDatabase(_))
+        ));
+    }
+
+    #[test]
+    fn force_skips_the_header_check() {
+        let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F', 0x00];
+        assert!(validate_database(&jpeg, true).is_ok());
+    }
 }
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Advisory `<path>.lock` file recording which process has a database open.
+// Nothing stops another program from writing the file; brewcode instances
+// just agree to check it before opening.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    // Unix millis when the lock was taken
+    pub since: Option<u64>,
+}
+
+pub fn lock_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+fn read(target: &Path) -> Option<LockInfo> {
+    read_lock_file(&lock_path(target))
+}
+
+// Contents of a lock file, or None if `lock_file` isn't one of ours
+pub fn read_lock_file(lock_file: &Path) -> Option<LockInfo> {
+    let contents = fs::read(lock_file).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+pub fn process_alive(pid: u32) -> bool {
+    use sysinfo::{Pid, ProcessesToUpdate, System};
+
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    system.process(pid).is_some()
+}
+
+// The lock held on `target` by another running process, if any. Locks we own,
+// unreadable lock files and locks whose process has exited don't count.
+pub fn held_elsewhere(target: &Path) -> Option<LockInfo> {
+    read(target).filter(|info| info.pid != std::process::id() && process_alive(info.pid))
+}
+
+// Write our own lock for `target`. Callers check `held_elsewhere` first.
+pub fn acquire(target: &Path, now_millis: Option<u64>) -> io::Result<()> {
+    let info = LockInfo {
+        pid: std::process::id(),
+        since: now_millis,
+    };
+    let contents = serde_json::to_vec(&info).map_err(io::Error::from)?;
+    crate::storage::atomic_write(&lock_path(target), &contents)
+}
+
+// Remove our lock on `target`; someone else's lock is left alone
+pub fn release(target: &Path) {
+    if read(target).is_some_and(|info| info.pid == std::process::id()) {
+        let _ = fs::remove_file(lock_path(target));
+    }
+}
+
+// Remove the lock file whoever owns it
+pub fn force_release(target: &Path) -> io::Result<()> {
+    match fs::remove_file(lock_path(target)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,201 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::BrewError;
+use crate::sqlite::{attach_read_only, quote_ident, user_tables};
+
+// What to do with a row whose primary key already exists in the current database
+#[derive(Clone, Copy, Deserialize)]
+pub enum MergeStrategy {
+    Skip,
+    Replace,
+    Error,
+}
+
+#[derive(Serialize)]
+pub struct TableMergeReport {
+    pub table: String,
+    pub inserted: u64,
+    pub skipped: u64,
+    pub replaced: u64,
+}
+
+#[derive(Serialize)]
+pub struct MergeReport {
+    pub tables: Vec<TableMergeReport>,
+    pub inserted: u64,
+    pub skipped: u64,
+    pub replaced: u64,
+}
+
+// Column names of `table` in `schema`, lowercased, plus its primary key columns
+fn columns(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+) -> rusqlite::Result<(Vec<String>, Vec<String>)> {
+    let mut stmt = conn.prepare("SELECT name, pk FROM pragma_table_info(?1, ?2) ORDER BY cid")?;
+    let rows = stmt
+        .query_map([table, schema], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut pk: Vec<(i64, String)> = rows
+        .iter()
+        .filter(|(_, position)| *position > 0)
+        .map(|(name, position)| (*position, name.clone()))
+        .collect();
+    pk.sort();
+    Ok((
+        rows.into_iter()
+            .map(|(name, _)| name.to_lowercase())
+            .collect(),
+        pk.into_iter().map(|(_, name)| name).collect(),
+    ))
+}
+
+// Copy the rows of every table that exists in both databases from the file
+// at `other` into `conn`, all in one transaction. Tables only one side has
+// are left alone; a shared table whose columns differ fails the whole merge.
+pub fn merge(
+    conn: &mut Connection,
+    other: &Path,
+    strategy: MergeStrategy,
+) -> Result<MergeReport, BrewError> {
+    attach_read_only(conn, other, "merge_source")?;
+    let result = merge_attached(conn, strategy);
+    // ATTACH can't happen inside the transaction, so always undo it here
+    let detached = conn.execute_batch("DETACH DATABASE merge_source");
+    let report = result?;
+    detached?;
+    Ok(report)
+}
+
+fn merge_attached(
+    conn: &mut Connection,
+    strategy: MergeStrategy,
+) -> Result<MergeReport, BrewError> {
+    let source_tables: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT name FROM merge_source.sqlite_master \
+             WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'",
+        )?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        names
+    };
+    let shared: Vec<String> = user_tables(conn)?
+        .into_iter()
+        .filter(|table| {
+            source_tables
+                .iter()
+                .any(|other| other.eq_ignore_ascii_case(table))
+        })
+        .collect();
+
+    let tx = conn.transaction()?;
+    // Parents and children are copied in name order; check references at commit
+    tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+
+    let mut report = MergeReport {
+        tables: Vec::new(),
+        inserted: 0,
+        skipped: 0,
+        replaced: 0,
+    };
+    for table in shared {
+        let (mut ours, pk) = columns(&tx, "main", &table)?;
+        let (mut theirs, _) = columns(&tx, "merge_source", &table)?;
+        let names = ours.clone();
+        ours.sort();
+        theirs.sort();
+        if ours != theirs {
+            return Err(BrewError::SchemaMismatch {
+                table,
+                reason: format!(
+                    "its columns are ({}) here but ({}) in the other database",
+                    ours.join(", "),
+                    theirs.join(", ")
+                ),
+            });
+        }
+
+        let quoted = quote_ident(&table);
+        let column_list: Vec<String> = names.iter().map(|name| quote_ident(name)).collect();
+        let column_list = column_list.join(", ");
+        let total: u64 = tx.query_row(
+            &format!("SELECT count(*) FROM merge_source.{}", quoted),
+            [],
+            |row| row.get::<_, i64>(0),
+        )? as u64;
+        // Rows of theirs whose primary key we already have. Without a declared
+        // key the rows get fresh rowids and never collide.
+        let conflicts: u64 = if pk.is_empty() {
+            0
+        } else {
+            let matches: Vec<String> = pk
+                .iter()
+                .map(|name| format!("m.{0} IS o.{0}", quote_ident(name)))
+                .collect();
+            tx.query_row(
+                &format!(
+                    "SELECT count(*) FROM merge_source.{0} o WHERE EXISTS \
+                     (SELECT 1 FROM main.{0} m WHERE {1})",
+                    quoted,
+                    matches.join(" AND ")
+                ),
+                [],
+                |row| row.get::<_, i64>(0),
+            )? as u64
+        };
+
+        let verb = match strategy {
+            MergeStrategy::Skip => "INSERT OR IGNORE",
+            MergeStrategy::Replace => "INSERT OR REPLACE",
+            MergeStrategy::Error if conflicts > 0 => {
+                return Err(BrewError::MergeConflict {
+                    table,
+                    rows: conflicts,
+                });
+            }
+            MergeStrategy::Error => "INSERT",
+        };
+        let changed = tx.execute(
+            &format!(
+                "{0} INTO main.{1} ({2}) SELECT {2} FROM merge_source.{1}",
+                verb, quoted, column_list
+            ),
+            [],
+        )? as u64;
+
+        let entry = match strategy {
+            MergeStrategy::Skip => TableMergeReport {
+                table,
+                inserted: changed,
+                skipped: total - changed,
+                replaced: 0,
+            },
+            MergeStrategy::Replace => TableMergeReport {
+                table,
+                inserted: total - conflicts,
+                skipped: 0,
+                replaced: conflicts,
+            },
+            MergeStrategy::Error => TableMergeReport {
+                table,
+                inserted: changed,
+                skipped: 0,
+                replaced: 0,
+            },
+        };
+        report.inserted += entry.inserted;
+        report.skipped += entry.skipped;
+        report.replaced += entry.replaced;
+        report.tables.push(entry);
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
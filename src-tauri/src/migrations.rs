@@ -0,0 +1,120 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BrewError;
+
+// The same scripts the browser build runs in `initDb`
+const SCHEMA_SQL: &str = include_str!("../../public/database/schema.sql");
+const SEED_SQL: &str = include_str!("../../public/database/seedData.sql");
+
+// Schema upgrades, applied in order. Entry `n` takes a file from
+// `user_version` n to n + 1. Never edit a released entry; add a new one.
+// Version 1 is the base schema, whose `IF NOT EXISTS`/`OR IGNORE` statements
+// leave files created before versioning unchanged.
+const MIGRATIONS: &[&str] = &[SCHEMA_SQL];
+
+// Reverse of each migration, by the same index: entry `n` takes a file from
+// `user_version` n + 1 back to n. `None` marks a step that can't be undone.
+// Must stay the same length as `MIGRATIONS`.
+const DOWNGRADES: &[Option<&str>] = &[
+    // Dropping the base schema would leave nothing to hand over
+    None,
+];
+const _: () = assert!(DOWNGRADES.len() == MIGRATIONS.len());
+
+// Newest schema version this build understands
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Serialize, Deserialize)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+}
+
+// `user_version` straight from the file header (big-endian u32 at offset 60)
+pub fn header_version(data: &[u8]) -> Option<u32> {
+    let bytes = data.get(60..64)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn ensure_supported(version: u32) -> Result<(), BrewError> {
+    if version > LATEST_VERSION {
+        Err(BrewError::DatabaseTooNew {
+            version,
+            supported: LATEST_VERSION,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+// Apply every pending migration in one transaction, so a failure leaves the
+// file at its old version
+pub fn migrate(conn: &mut Connection) -> Result<MigrationReport, BrewError> {
+    let from: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    ensure_supported(from)?;
+
+    let tx = conn.transaction()?;
+    for sql in &MIGRATIONS[from as usize..] {
+        tx.execute_batch(sql)?;
+    }
+    tx.execute_batch(&format!("PRAGMA user_version = {}", LATEST_VERSION))?;
+    tx.commit()?;
+
+    Ok(MigrationReport {
+        from,
+        to: LATEST_VERSION,
+    })
+}
+
+// Move a database to exactly `target`: forward through `MIGRATIONS` or back
+// through `DOWNGRADES`, in one transaction. Meant for copies handed to older
+// brewcode builds, never the user's own file.
+pub fn convert(conn: &mut Connection, target: u32) -> Result<MigrationReport, BrewError> {
+    let from: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    ensure_supported(from)?;
+    ensure_supported(target)?;
+
+    let tx = conn.transaction()?;
+    if target >= from {
+        for sql in &MIGRATIONS[from as usize..target as usize] {
+            tx.execute_batch(sql)?;
+        }
+    } else {
+        for step in (target..from).rev() {
+            match DOWNGRADES[step as usize] {
+                Some(sql) => tx.execute_batch(sql)?,
+                None => {
+                    return Err(BrewError::DowngradeNotSupported {
+                        from: step + 1,
+                        to: step,
+                    })
+                }
+            }
+        }
+    }
+    tx.execute_batch(&format!("PRAGMA user_version = {}", target))?;
+    tx.commit()?;
+
+    Ok(MigrationReport { from, to: target })
+}
+
+// A fresh database at the latest schema with seed data, as file bytes
+pub fn new_database() -> Result<Vec<u8>, BrewError> {
+    fresh_database(Some(SEED_SQL))
+}
+
+// A fresh database at the latest schema without the seed data, as file bytes
+pub fn empty_database() -> Result<Vec<u8>, BrewError> {
+    fresh_database(None)
+}
+
+fn fresh_database(seed: Option<&str>) -> Result<Vec<u8>, BrewError> {
+    let mut conn = Connection::open_in_memory()?;
+    migrate(&mut conn)?;
+    if let Some(seed) = seed {
+        conn.execute_batch(seed)?;
+    }
+    let data = conn.serialize(rusqlite::MAIN_DB)?;
+    Ok(data.to_vec())
+}
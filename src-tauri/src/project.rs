@@ -0,0 +1,141 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor, Read, Write};
+
+use crate::error::BrewError;
+use crate::settings::{PartialSettings, Settings};
+use crate::storage::sha256_hex;
+
+// A project is a plain zip of the database, the app settings and the view
+// state, plus a manifest, for moving a whole workspace between machines
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "database.db";
+const SETTINGS_ENTRY: &str = "settings.json";
+const VIEW_STATE_ENTRY: &str = "viewstate.json";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectManifest {
+    pub format: u32,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub created_at: Option<u64>,
+    pub app_version: String,
+}
+
+// The only settings a project carries. Anything that loosens safety checks or
+// names a local path (`allow_writes`, `mirror_path` and the like) stays with
+// the machine, so opening someone else's project can't change it. Other
+// fields in `settings.json`, as written by older versions, are ignored.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectSettings {
+    pub autosave_interval: Option<u32>,
+    pub snapshot_depth: Option<usize>,
+}
+
+impl ProjectSettings {
+    pub fn from_settings(settings: &Settings) -> Self {
+        ProjectSettings {
+            autosave_interval: settings.autosave_interval,
+            snapshot_depth: Some(settings.snapshot_depth),
+        }
+    }
+
+    // The change opening the project makes to the user's settings
+    pub fn to_partial(&self) -> PartialSettings {
+        PartialSettings {
+            autosave_interval: Some(self.autosave_interval.unwrap_or(0)),
+            snapshot_depth: self.snapshot_depth,
+            ..PartialSettings::default()
+        }
+    }
+}
+
+pub struct Project<V> {
+    pub data: Vec<u8>,
+    pub settings: ProjectSettings,
+    pub view_state: Option<V>,
+    pub manifest: ProjectManifest,
+}
+
+fn archive_error(e: impl std::fmt::Display) -> BrewError {
+    BrewError::Io(io::Error::other(e.to_string()))
+}
+
+fn malformed(reason: &str) -> BrewError {
+    BrewError::InvalidDatabase(format!("not a valid brewcode project: {}", reason))
+}
+
+// `viewstate.json` holds `null` when there is no view state to carry over
+pub fn pack<V: Serialize>(
+    database: &[u8],
+    settings: &Settings,
+    view_state: Option<&V>,
+    created_at: Option<u64>,
+) -> Result<Vec<u8>, BrewError> {
+    let manifest = ProjectManifest {
+        format: FORMAT_VERSION,
+        sha256: sha256_hex(database),
+        size_bytes: database.len() as u64,
+        created_at,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(MANIFEST_ENTRY, options)
+        .map_err(archive_error)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest).map_err(archive_error)?;
+    zip.start_file(SETTINGS_ENTRY, options)
+        .map_err(archive_error)?;
+    serde_json::to_writer_pretty(&mut zip, &ProjectSettings::from_settings(settings))
+        .map_err(archive_error)?;
+    zip.start_file(VIEW_STATE_ENTRY, options)
+        .map_err(archive_error)?;
+    serde_json::to_writer_pretty(&mut zip, &view_state).map_err(archive_error)?;
+    zip.start_file(DATABASE_ENTRY, options)
+        .map_err(archive_error)?;
+    zip.write_all(database)?;
+    Ok(zip.finish().map_err(archive_error)?.into_inner())
+}
+
+fn read_json<T: DeserializeOwned>(
+    zip: &mut zip::ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> Result<T, BrewError> {
+    let entry = zip
+        .by_name(name)
+        .map_err(|_| malformed(&format!("{} is missing", name)))?;
+    serde_json::from_reader(entry).map_err(|e| malformed(&format!("{}: {}", name, e)))
+}
+
+// Unpack a project, checking every entry is present and the database matches
+// the checksum recorded when it was packed
+pub fn unpack<V: DeserializeOwned>(archive: &[u8]) -> Result<Project<V>, BrewError> {
+    let mut zip =
+        zip::ZipArchive::new(Cursor::new(archive)).map_err(|e| malformed(&e.to_string()))?;
+
+    let manifest: ProjectManifest = read_json(&mut zip, MANIFEST_ENTRY)?;
+    if manifest.format > FORMAT_VERSION {
+        return Err(malformed("it was made by a newer version of brewcode"));
+    }
+    let settings = read_json(&mut zip, SETTINGS_ENTRY)?;
+    let view_state = read_json(&mut zip, VIEW_STATE_ENTRY)?;
+
+    let mut data = Vec::with_capacity(manifest.size_bytes as usize);
+    zip.by_name(DATABASE_ENTRY)
+        .map_err(|_| malformed("database.db is missing"))?
+        .read_to_end(&mut data)?;
+    if sha256_hex(&data) != manifest.sha256 {
+        return Err(malformed("the database doesn't match its checksum"));
+    }
+
+    Ok(Project {
+        data,
+        settings,
+        view_state,
+        manifest,
+    })
+}
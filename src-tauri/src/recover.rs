@@ -0,0 +1,221 @@
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::error::BrewError;
+use crate::sqlite::{open_bytes, quote_ident, row_count};
+
+#[derive(Serialize)]
+pub struct TableRecovery {
+    pub table: String,
+    pub recovered: u64,
+    // Rows the damaged file claims to have that couldn't be read back; None
+    // when even counting them failed
+    pub lost: Option<u64>,
+}
+
+// Counts are best-effort: a damaged page can hide rows that nothing in the
+// file still mentions, so `rows_lost` is a lower bound
+#[derive(Serialize)]
+pub struct RecoveryReport {
+    pub tables: Vec<TableRecovery>,
+    pub tables_lost: Vec<String>,
+    pub objects_skipped: Vec<String>,
+    pub rows_recovered: u64,
+    pub rows_lost: u64,
+}
+
+pub struct Recovered {
+    pub data: Vec<u8>,
+    pub report: RecoveryReport,
+}
+
+struct SchemaEntry {
+    kind: String,
+    name: String,
+    sql: String,
+}
+
+// Read schema entries until the first unreadable one
+fn schema_entries(source: &Connection) -> rusqlite::Result<Vec<SchemaEntry>> {
+    let mut stmt = source.prepare(
+        "SELECT type, name, sql FROM sqlite_master \
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\'",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut entries = Vec::new();
+    loop {
+        match rows.next() {
+            Ok(Some(row)) => entries.push(SchemaEntry {
+                kind: row.get(0)?,
+                name: row.get(1)?,
+                sql: row.get(2)?,
+            }),
+            Ok(None) => break,
+            Err(e) if entries.is_empty() => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(entries)
+}
+
+// Copy readable rows of `select` into `insert`, stopping at the first error
+// or, with `stop_at`, at that rowid. Returns the rows copied and the last
+// rowid seen, taken from the first selected column.
+fn copy_rows(
+    source: &Connection,
+    target: &Connection,
+    select: &str,
+    insert: &str,
+    stop_at: Option<i64>,
+) -> (u64, Option<i64>) {
+    let (mut copied, mut last_rowid) = (0, None);
+    let Ok(mut stmt) = source.prepare(select) else {
+        return (copied, last_rowid);
+    };
+    let Ok(mut insert) = target.prepare(insert) else {
+        return (copied, last_rowid);
+    };
+    let width = stmt.column_count();
+    let Ok(mut rows) = stmt.query([]) else {
+        return (copied, last_rowid);
+    };
+    while let Ok(Some(row)) = rows.next() {
+        let Ok(values) = (0..width)
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<rusqlite::Result<Vec<_>>>()
+        else {
+            break;
+        };
+        if let (Value::Integer(rowid), Some(stop)) = (&values[0], stop_at) {
+            if *rowid == stop {
+                break;
+            }
+        }
+        if insert.execute(rusqlite::params_from_iter(&values)).is_err() {
+            break;
+        }
+        if let Value::Integer(rowid) = values[0] {
+            last_rowid = Some(rowid);
+        }
+        copied += 1;
+    }
+    (copied, last_rowid)
+}
+
+// Copy every readable row of `table`. Rowid tables are read forwards until
+// the damage and then backwards from the end, so rows after a bad page
+// survive too; `INSERT OR IGNORE` drops rows both passes reached.
+fn recover_table(source: &Connection, target: &Connection, table: &str) -> rusqlite::Result<u64> {
+    let mut stmt = target.prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?;
+    let columns = stmt
+        .query_map([table], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let list = columns
+        .iter()
+        .map(|name| quote_ident(name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let quoted = quote_ident(table);
+    let has_rowid = source
+        .prepare(&format!("SELECT rowid FROM {} LIMIT 0", quoted))
+        .is_ok();
+
+    if !has_rowid {
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let (copied, _) = copy_rows(
+            source,
+            target,
+            &format!("SELECT {} FROM {}", list, quoted),
+            &format!(
+                "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+                quoted, list, placeholders
+            ),
+            None,
+        );
+        return Ok(copied);
+    }
+
+    let placeholders = vec!["?"; columns.len() + 1].join(", ");
+    let insert = format!(
+        "INSERT OR IGNORE INTO {} (rowid, {}) VALUES ({})",
+        quoted, list, placeholders
+    );
+    let select = format!("SELECT rowid, {} FROM {} ORDER BY rowid", list, quoted);
+    let (forward, last_rowid) = copy_rows(source, target, &select, &insert, None);
+    let (backward, _) = copy_rows(
+        source,
+        target,
+        &format!("{} DESC", select),
+        &insert,
+        last_rowid,
+    );
+    Ok(forward + backward)
+}
+
+// Best-effort rescue of a damaged database: recreate its schema in a fresh
+// in-memory database and copy over every row that can still be read.
+// `data` is never modified. Fails only if not even the schema is readable.
+pub fn recover(data: &[u8]) -> Result<Recovered, BrewError> {
+    let source = open_bytes(data)?;
+    let entries = schema_entries(&source).map_err(|e| {
+        BrewError::InvalidDatabase(format!(
+            "the schema can't be read, so nothing can be recovered: {}",
+            e
+        ))
+    })?;
+
+    let target = Connection::open_in_memory()?;
+    // Recovered rows may point at rows that were lost
+    target.execute_batch("PRAGMA foreign_keys = OFF; BEGIN")?;
+
+    let mut report = RecoveryReport {
+        tables: Vec::new(),
+        tables_lost: Vec::new(),
+        objects_skipped: Vec::new(),
+        rows_recovered: 0,
+        rows_lost: 0,
+    };
+    // Virtual tables would need their module; their shadow tables are
+    // recovered as plain tables instead
+    let (tables, others): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| {
+        entry.kind == "table"
+            && !entry
+                .sql
+                .trim_start()
+                .to_ascii_uppercase()
+                .starts_with("CREATE VIRTUAL")
+    });
+
+    for entry in tables {
+        if target.execute_batch(&entry.sql).is_err() {
+            report.tables_lost.push(entry.name);
+            continue;
+        }
+        let recovered = recover_table(&source, &target, &entry.name)?;
+        let lost = row_count(&source, &entry.name)
+            .ok()
+            .map(|count| count.saturating_sub(recovered));
+        report.rows_recovered += recovered;
+        report.rows_lost += lost.unwrap_or(0);
+        report.tables.push(TableRecovery {
+            table: entry.name,
+            recovered,
+            lost,
+        });
+    }
+
+    // Indexes, views and triggers go in after the data so triggers don't fire
+    // during the copy
+    for entry in others {
+        if target.execute_batch(&entry.sql).is_err() {
+            report.objects_skipped.push(entry.name);
+        }
+    }
+    target.execute_batch("COMMIT")?;
+
+    Ok(Recovered {
+        data: target.serialize(rusqlite::MAIN_DB)?.to_vec(),
+        report,
+    })
+}
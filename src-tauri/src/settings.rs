@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{Durability, TempStrategy};
+
+// Every user preference, persisted together as `settings.json`. Fields
+// missing from an older file take their defaults, so adding one never
+// breaks an existing config.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Rolling backups kept when saving over an existing file
+    pub backup_count: u8,
+    // Seconds between `autosave-tick` events; None when autosave is off
+    pub autosave_interval: Option<u32>,
+    // Undo snapshots kept before the oldest are dropped
+    pub snapshot_depth: usize,
+    // Rows `import_csv` may skip before it rolls the whole import back
+    pub import_max_failed_rows: u64,
+    // Seconds a save or export may take before failing with `Timeout`
+    pub save_timeout_secs: u64,
+    pub temp_strategy: TempStrategy,
+    pub durability: Durability,
+    // Let saves and exports go into app and system folders
+    pub allow_unsafe_location: bool,
+    // Cache unsaved bytes from `mark_dirty` for crash recovery
+    pub journal_enabled: bool,
+    pub journal_max_bytes: u64,
+    pub reopen_last_on_launch: bool,
+    // Let `run_write` change the current file
+    pub allow_writes: bool,
+    // Files bigger than this are only opened when the caller insists
+    pub max_open_bytes: u64,
+    // Write `<file>.sha256` next to each save
+    pub write_checksum_sidecar: bool,
+    // Folder every save is also copied into, under the same file name
+    pub mirror_path: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backup_count: 3,
+            autosave_interval: None,
+            snapshot_depth: 20,
+            import_max_failed_rows: 10,
+            save_timeout_secs: 30,
+            temp_strategy: TempStrategy::SameDir,
+            durability: Durability::Full,
+            allow_unsafe_location: false,
+            journal_enabled: false,
+            journal_max_bytes: 64 * 1024 * 1024,
+            reopen_last_on_launch: false,
+            allow_writes: false,
+            max_open_bytes: 2 * 1024 * 1024 * 1024,
+            write_checksum_sidecar: false,
+            mirror_path: None,
+        }
+    }
+}
+
+// The settings an `update_settings` call changes; absent fields are kept.
+// An `autosave_interval` of 0 turns autosave off, as does an empty
+// `mirror_path` for mirroring.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct PartialSettings {
+    pub backup_count: Option<u8>,
+    pub autosave_interval: Option<u32>,
+    pub snapshot_depth: Option<usize>,
+    pub import_max_failed_rows: Option<u64>,
+    pub save_timeout_secs: Option<u64>,
+    pub temp_strategy: Option<TempStrategy>,
+    pub durability: Option<Durability>,
+    pub allow_unsafe_location: Option<bool>,
+    pub journal_enabled: Option<bool>,
+    pub journal_max_bytes: Option<u64>,
+    pub reopen_last_on_launch: Option<bool>,
+    pub allow_writes: Option<bool>,
+    pub max_open_bytes: Option<u64>,
+    pub write_checksum_sidecar: Option<bool>,
+    pub mirror_path: Option<String>,
+}
+
+impl Settings {
+    // `self` with the fields `partial` sets replaced, clamped to usable values
+    pub fn merged(&self, partial: PartialSettings) -> Settings {
+        Settings {
+            backup_count: partial.backup_count.unwrap_or(self.backup_count),
+            autosave_interval: match partial.autosave_interval {
+                Some(secs) => Some(secs).filter(|secs| *secs > 0),
+                None => self.autosave_interval,
+            },
+            snapshot_depth: partial.snapshot_depth.unwrap_or(self.snapshot_depth),
+            import_max_failed_rows: partial
+                .import_max_failed_rows
+                .unwrap_or(self.import_max_failed_rows),
+            save_timeout_secs: partial
+                .save_timeout_secs
+                .map_or(self.save_timeout_secs, |secs| secs.max(1)),
+            temp_strategy: partial.temp_strategy.unwrap_or(self.temp_strategy),
+            durability: partial.durability.unwrap_or(self.durability),
+            allow_unsafe_location: partial
+                .allow_unsafe_location
+                .unwrap_or(self.allow_unsafe_location),
+            journal_enabled: partial.journal_enabled.unwrap_or(self.journal_enabled),
+            journal_max_bytes: partial.journal_max_bytes.unwrap_or(self.journal_max_bytes),
+            reopen_last_on_launch: partial
+                .reopen_last_on_launch
+                .unwrap_or(self.reopen_last_on_launch),
+            allow_writes: partial.allow_writes.unwrap_or(self.allow_writes),
+            max_open_bytes: partial.max_open_bytes.unwrap_or(self.max_open_bytes),
+            write_checksum_sidecar: partial
+                .write_checksum_sidecar
+                .unwrap_or(self.write_checksum_sidecar),
+            mirror_path: match partial.mirror_path {
+                Some(path) => Some(path).filter(|path| !path.is_empty()),
+                None => self.mirror_path.clone(),
+            },
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::BrewError;
+
+// `file:` URI for `path`, percent-encoding everything SQLite's URI parser
+// could misread (`?`, `#`, `%`, spaces, non-ASCII bytes)
+pub fn file_uri(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().replace('\\', "/").into_bytes();
+
+    let mut uri = String::from("file:");
+    if bytes.get(1) == Some(&b':') {
+        // Windows drive letter: file:///C:/...
+        uri.push_str("///");
+    }
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+// Open a database strictly for reading. `mode=ro` still takes shared locks
+// and reads the WAL, so while the live connection writes, reads see what it
+// has committed rather than a stale or half-written file.
+pub fn open_read_only(path: &Path) -> rusqlite::Result<Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    Connection::open_with_flags(format!("{}?mode=ro", file_uri(path)), flags)
+}
+
+// Read-only in-memory connection over a copy of `data`, for looking
+// inside bytes that aren't on disk
+pub fn open_bytes(data: &[u8]) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open_in_memory()?;
+    conn.deserialize_read_exact(rusqlite::MAIN_DB, data, data.len(), true)?;
+    Ok(conn)
+}
+
+// Writable in-memory copy of `data`; changes never reach the original
+pub fn open_bytes_mut(data: &[u8]) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open_in_memory()?;
+    conn.deserialize_read_exact(rusqlite::MAIN_DB, data, data.len(), false)?;
+    Ok(conn)
+}
+
+// Attach the database at `path` to `conn` as `alias`. `mode=ro` keeps it
+// read-only even on a writable connection.
+pub fn attach_read_only(conn: &Connection, path: &Path, alias: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!("ATTACH DATABASE ?1 AS {}", quote_ident(alias)),
+        [format!("{}?mode=ro", file_uri(path))],
+    )?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    // More rows matched than were returned
+    pub truncated: bool,
+}
+
+// One page of a table for a virtualized grid. `truncated` means more rows
+// follow this page.
+#[derive(Serialize)]
+pub struct TablePage {
+    #[serde(flatten)]
+    pub page: QueryResult,
+    pub total: u64,
+}
+
+// `limit` rows of `table` starting at `offset`, sorted by `order_by` or else
+// by primary key so pages don't overlap. Both names are checked against the
+// schema before going into the SQL.
+pub fn table_page(
+    conn: &Connection,
+    table: &str,
+    offset: u64,
+    limit: u32,
+    order_by: Option<&str>,
+) -> Result<TablePage, BrewError> {
+    if !user_tables(conn)?.iter().any(|name| name == table) {
+        return Err(BrewError::UnknownTable(table.to_string()));
+    }
+    let order = match order_by {
+        Some(column) => {
+            if !table_columns(conn, table)?.iter().any(|c| c.name == column) {
+                return Err(BrewError::InvalidQuery(format!(
+                    "table '{}' has no column '{}'",
+                    table, column
+                )));
+            }
+            format!(" ORDER BY {}", quote_ident(column))
+        }
+        None => crate::export::row_order(conn, table)?,
+    };
+
+    let sql = format!(
+        "SELECT * FROM {}{} LIMIT ?1 OFFSET ?2",
+        quote_ident(table),
+        order
+    );
+    // An offset past i64::MAX would wrap negative, which SQLite reads as 0
+    let sql_offset = i64::try_from(offset).unwrap_or(i64::MAX);
+    let params = [Value::Integer(limit as i64), Value::Integer(sql_offset)];
+    let mut page = query(conn, &sql, &params, limit as usize)?;
+    let total = row_count(conn, table)?;
+    page.truncated = offset + (page.rows.len() as u64) < total;
+    Ok(TablePage { page, total })
+}
+
+// Rows read to work out the average row size in `estimate_table_size`
+const SIZE_SAMPLE_ROWS: u32 = 1_000;
+
+// Rough size of a table's data once loaded
+#[derive(Serialize)]
+pub struct SizeEstimate {
+    pub row_count: u64,
+    pub sampled_rows: u64,
+    // Bytes of values per row over the sample: 8 per number, the length of
+    // each text or blob
+    pub avg_row_bytes: u64,
+    pub estimated_bytes: u64,
+}
+
+// Estimate how much `table` holds from its row count and the first
+// `SIZE_SAMPLE_ROWS` rows, so a huge table doesn't have to be read in full
+pub fn estimate_table_size(conn: &Connection, table: &str) -> Result<SizeEstimate, BrewError> {
+    if !user_tables(conn)?.iter().any(|name| name == table) {
+        return Err(BrewError::UnknownTable(table.to_string()));
+    }
+    let row_count = row_count(conn, table)?;
+
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {} LIMIT ?1", quote_ident(table)))?;
+    let column_count = stmt.column_count();
+    let mut rows = stmt.query([SIZE_SAMPLE_ROWS])?;
+    let (mut sampled_rows, mut sampled_bytes) = (0u64, 0u64);
+    while let Some(row) = rows.next()? {
+        for i in 0..column_count {
+            sampled_bytes += match row.get_ref(i)? {
+                ValueRef::Null => 0,
+                ValueRef::Integer(_) | ValueRef::Real(_) => 8,
+                ValueRef::Text(bytes) | ValueRef::Blob(bytes) => bytes.len() as u64,
+            };
+        }
+        sampled_rows += 1;
+    }
+
+    let avg_row_bytes = sampled_bytes.checked_div(sampled_rows).unwrap_or(0);
+    Ok(SizeEstimate {
+        row_count,
+        sampled_rows,
+        avg_row_bytes,
+        estimated_bytes: avg_row_bytes.saturating_mul(row_count),
+    })
+}
+
+// SQL value for a JSON query parameter; booleans become 0 and 1
+pub fn param_value(value: &serde_json::Value) -> Result<Value, BrewError> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(text) => Value::Text(text.clone()),
+        _ => {
+            return Err(BrewError::InvalidQuery(
+                "parameters must be null, a boolean, a number or a string".to_string(),
+            ))
+        }
+    })
+}
+
+// Run a single statement that doesn't write, binding `params` to its
+// placeholders in order, and return up to `max_rows` rows with values as
+// JSON (blobs base64)
+pub fn query(
+    conn: &Connection,
+    sql: &str,
+    params: &[Value],
+    max_rows: usize,
+) -> Result<QueryResult, BrewError> {
+    let mut stmt = conn.prepare(sql)?;
+    if !stmt.readonly() {
+        return Err(BrewError::InvalidQuery(
+            "only statements that read data are allowed".to_string(),
+        ));
+    }
+    if stmt.parameter_count() != params.len() {
+        return Err(BrewError::InvalidQuery(format!(
+            "the statement takes {} parameters but {} were given",
+            stmt.parameter_count(),
+            params.len()
+        )));
+    }
+    let columns: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let width = columns.len();
+    let mut result = QueryResult {
+        columns,
+        rows: Vec::new(),
+        truncated: false,
+    };
+
+    let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+    while let Some(row) = rows.next()? {
+        if result.rows.len() == max_rows {
+            result.truncated = true;
+            break;
+        }
+        let values = (0..width)
+            .map(|i| row.get_ref(i).map(crate::export::json_value))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        result.rows.push(values);
+    }
+    Ok(result)
+}
+
+// The SQLite library compiled into brewcode, for support reports
+#[derive(Serialize)]
+pub struct SqliteBuildInfo {
+    pub version: String,
+    pub source_id: String,
+    pub compile_options: Vec<String>,
+}
+
+pub fn build_info() -> rusqlite::Result<SqliteBuildInfo> {
+    let conn = Connection::open_in_memory()?;
+    let source_id = conn.query_row("SELECT sqlite_source_id()", [], |row| row.get(0))?;
+    let mut stmt = conn.prepare("PRAGMA compile_options")?;
+    let compile_options = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(SqliteBuildInfo {
+        version: rusqlite::version().to_string(),
+        source_id,
+        compile_options,
+    })
+}
+
+#[derive(Serialize)]
+pub struct WriteResult {
+    // Rows each statement changed, in order
+    pub rows_affected: Vec<u64>,
+}
+
+// Run `statements` in one transaction, rolling all of them back if any fails.
+// Statements that don't write are refused before they run; that includes
+// BEGIN, COMMIT and the like, which would end the transaction early.
+pub fn execute_all(conn: &mut Connection, statements: &[String]) -> Result<WriteResult, BrewError> {
+    let tx = conn.transaction()?;
+    let mut rows_affected = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        let failed = |reason: String| BrewError::StatementFailed { index, reason };
+        let mut stmt = tx.prepare(statement).map_err(|e| failed(e.to_string()))?;
+        if stmt.readonly() {
+            return Err(failed(
+                "only statements that change data are allowed here".to_string(),
+            ));
+        }
+        let changed = stmt.execute([]).map_err(|e| failed(e.to_string()))?;
+        rows_affected.push(changed as u64);
+    }
+    tx.commit()?;
+    Ok(WriteResult { rows_affected })
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    pub messages: Vec<String>,
+}
+
+// Run `PRAGMA integrity_check`; SQLite reports a single "ok" row when healthy
+pub fn integrity_check(conn: &Connection) -> rusqlite::Result<IntegrityReport> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(IntegrityReport {
+        ok: messages.len() == 1 && messages[0] == "ok",
+        messages,
+    })
+}
+
+// Quote an identifier for interpolation into SQL
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// User tables in name order, skipping SQLite's internal `sqlite_*` tables
+pub fn user_tables(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master \
+         WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+// Whether `name` is a table or view in the file
+pub fn has_table_or_view(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TableInfo {
+    pub name: String,
+    pub columns: Vec<ColumnInfo>,
+    pub row_count: u64,
+}
+
+pub fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<ColumnInfo>> {
+    let mut stmt = conn.prepare("SELECT name, type, \"notnull\" FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| {
+            Ok(ColumnInfo {
+                name: row.get(0)?,
+                data_type: row.get(1)?,
+                nullable: !row.get::<_, bool>(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+pub fn row_count(conn: &Connection, table: &str) -> rusqlite::Result<u64> {
+    conn.query_row(
+        &format!("SELECT count(*) FROM {}", quote_ident(table)),
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count as u64)
+}
+
+pub fn schema(conn: &Connection) -> rusqlite::Result<Vec<TableInfo>> {
+    user_tables(conn)?
+        .into_iter()
+        .map(|name| {
+            Ok(TableInfo {
+                columns: table_columns(conn, &name)?,
+                row_count: row_count(conn, &name)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+// Writable connection to an existing database; never creates a new file
+pub fn open_read_write(path: &Path) -> rusqlite::Result<Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    Connection::open_with_flags(path, flags)
+}
+
+// Fold the whole WAL back into the main file and truncate it. Returns false
+// if another connection kept SQLite from checkpointing every frame.
+pub fn checkpoint_truncate(conn: &Connection) -> rusqlite::Result<bool> {
+    let busy: i64 = conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+    Ok(busy == 0)
+}
+
+// Mark serialized database bytes as using a rollback journal instead of WAL
+// (header bytes 18 and 19), so a copy doesn't switch to WAL when opened
+pub fn set_rollback_journal(data: &mut [u8]) {
+    if data.len() >= 20 {
+        data[18] = 1;
+        data[19] = 1;
+    }
+}
+
+// Rebuild the file without free pages. Fails inside an open transaction.
+pub fn vacuum(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("VACUUM")
+}
+
+// Rebuild every index from its table
+pub fn reindex(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("REINDEX")
+}
+
+// Refresh the statistics the query planner uses to pick indexes
+pub fn analyze(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("ANALYZE")
+}
+
+// Connection settings the UI may read and change
+#[derive(Serialize)]
+pub struct PragmaSettings {
+    pub journal_mode: String,
+    pub synchronous: i64,
+    pub page_size: i64,
+    pub cache_size: i64,
+    pub auto_vacuum: i64,
+    pub foreign_keys: bool,
+    pub busy_timeout: i64,
+}
+
+// Result of `set_pragma`: the value SQLite now reports, which for settings
+// that only apply after a VACUUM is still the old one
+#[derive(Serialize)]
+pub struct PragmaValue {
+    pub name: String,
+    pub value: String,
+    pub note: Option<&'static str>,
+}
+
+fn pragma_value<T: rusqlite::types::FromSql>(conn: &Connection, name: &str) -> rusqlite::Result<T> {
+    conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))
+}
+
+pub fn pragmas(conn: &Connection) -> rusqlite::Result<PragmaSettings> {
+    Ok(PragmaSettings {
+        journal_mode: pragma_value(conn, "journal_mode")?,
+        synchronous: pragma_value(conn, "synchronous")?,
+        page_size: pragma_value(conn, "page_size")?,
+        cache_size: pragma_value(conn, "cache_size")?,
+        auto_vacuum: pragma_value(conn, "auto_vacuum")?,
+        foreign_keys: pragma_value(conn, "foreign_keys")?,
+        busy_timeout: pragma_value(conn, "busy_timeout")?,
+    })
+}
+
+// How much of the file is free pages a VACUUM would give back
+#[derive(Serialize)]
+pub struct StorageStats {
+    pub page_count: u64,
+    pub freelist_count: u64,
+    pub page_size: u64,
+    pub wasted_bytes: u64,
+    // Free pages over all pages, 0 for an empty file
+    pub fragmentation_ratio: f64,
+}
+
+pub fn storage_stats(conn: &Connection) -> rusqlite::Result<StorageStats> {
+    let page_count: i64 = pragma_value(conn, "page_count")?;
+    let freelist_count: i64 = pragma_value(conn, "freelist_count")?;
+    let page_size: i64 = pragma_value(conn, "page_size")?;
+    let (page_count, freelist_count, page_size) =
+        (page_count as u64, freelist_count as u64, page_size as u64);
+    let fragmentation_ratio = match page_count {
+        0 => 0.0,
+        pages => freelist_count as f64 / pages as f64,
+    };
+    Ok(StorageStats {
+        page_count,
+        freelist_count,
+        page_size,
+        wasted_bytes: freelist_count * page_size,
+        fragmentation_ratio,
+    })
+}
+
+// Turn foreign-key enforcement on or off and return whether it's on now. The
+// change is silently ignored inside a transaction, hence reading it back.
+pub fn set_foreign_keys(conn: &Connection, enabled: bool) -> rusqlite::Result<bool> {
+    conn.execute_batch(if enabled {
+        "PRAGMA foreign_keys = ON"
+    } else {
+        "PRAGMA foreign_keys = OFF"
+    })?;
+    pragma_value(conn, "foreign_keys")
+}
+
+// A row whose foreign key points at no row of `parent`
+#[derive(Serialize)]
+pub struct FkViolation {
+    pub table: String,
+    // None for WITHOUT ROWID tables
+    pub rowid: Option<i64>,
+    pub parent: String,
+    // Which of the table's foreign keys, as numbered by `foreign_key_list`
+    pub fk_index: i64,
+}
+
+// Every foreign-key violation in the file, whether or not enforcement is on
+pub fn foreign_key_violations(conn: &Connection) -> rusqlite::Result<Vec<FkViolation>> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations = stmt
+        .query_map([], |row| {
+            Ok(FkViolation {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+                fk_index: row.get(3)?,
+            })
+        })?
+        .collect();
+    violations
+}
+
+// Check `value` against what `name` accepts and return it in the form it is
+// interpolated into the PRAGMA. Only these settings are allowed; none of them
+// can lose committed data, which is why `synchronous` can't be turned OFF:
+// a power loss could then drop committed transactions or corrupt the file.
+fn checked_pragma_value(name: &str, value: &str) -> Result<String, String> {
+    let upper = value.trim().to_ascii_uppercase();
+    let one_of = |allowed: &[&str]| {
+        if allowed.contains(&upper.as_str()) {
+            Ok(upper.clone())
+        } else {
+            Err(format!("{} must be one of {}", name, allowed.join(", ")))
+        }
+    };
+    let integer = || {
+        upper
+            .parse::<i64>()
+            .map_err(|_| format!("{} must be a whole number", name))
+    };
+
+    match name {
+        "journal_mode" => one_of(&["DELETE", "TRUNCATE", "PERSIST", "WAL"]),
+        "synchronous" => one_of(&["NORMAL", "FULL", "EXTRA", "1", "2", "3"]),
+        "auto_vacuum" => one_of(&["NONE", "FULL", "INCREMENTAL", "0", "1", "2"]),
+        "foreign_keys" => one_of(&["ON", "OFF", "TRUE", "FALSE", "1", "0"]),
+        "page_size" => match integer()? {
+            size if (512..=65536).contains(&size) && (size as u64).is_power_of_two() => {
+                Ok(size.to_string())
+            }
+            _ => Err("page_size must be a power of two from 512 to 65536".to_string()),
+        },
+        "cache_size" => integer().map(|size| size.to_string()),
+        "busy_timeout" => match integer()? {
+            ms if ms >= 0 => Ok(ms.to_string()),
+            _ => Err("busy_timeout can't be negative".to_string()),
+        },
+        _ => Err(format!("'{}' is not a setting brewcode can change", name)),
+    }
+}
+
+pub fn set_pragma(conn: &Connection, name: &str, value: &str) -> Result<PragmaValue, BrewError> {
+    let name = name.trim().to_ascii_lowercase();
+    let value = checked_pragma_value(&name, value).map_err(BrewError::InvalidPragma)?;
+    // journal_mode answers with a row; the others don't
+    conn.query_row(&format!("PRAGMA {} = {}", name, value), [], |_| Ok(()))
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(()),
+            e => Err(e),
+        })?;
+
+    let value = conn.query_row(&format!("PRAGMA {}", name), [], |row| {
+        Ok(match row.get_ref(0)? {
+            rusqlite::types::ValueRef::Integer(n) => n.to_string(),
+            rusqlite::types::ValueRef::Text(text) => String::from_utf8_lossy(text).to_string(),
+            _ => String::new(),
+        })
+    })?;
+    let note = match name.as_str() {
+        "page_size" | "auto_vacuum" => {
+            Some("Takes effect after the next VACUUM, and never while journal_mode is WAL")
+        }
+        _ => None,
+    };
+    Ok(PragmaValue { name, value, note })
+}
@@ -0,0 +1,914 @@
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const WRITE_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+// Every SQLite 3 database file starts with these 16 bytes
+pub const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+pub fn has_sqlite_header(data: &[u8]) -> bool {
+    data.starts_with(SQLITE_MAGIC)
+}
+
+// Where `atomic_write_cancellable` stages the new contents
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TempStrategy {
+    // Next to the target, so the final rename is atomic
+    #[default]
+    SameDir,
+    // In the OS temp directory, for folders that are slow or nearly full.
+    // Across filesystems the temp file is copied over the target instead of
+    // renamed, so a crash mid-copy can leave the target truncated.
+    SystemTemp,
+}
+
+// How hard a save works to survive power loss or a crash right after it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Durability {
+    // fsync the file and then its directory, so the rename itself is on disk
+    #[default]
+    Full,
+    // fsync the file only; after a crash the folder may still show the old
+    // file, but never a torn new one
+    Relaxed,
+    // No fsync at all. Fastest on slow network mounts, but a crash or power
+    // loss soon after saving can leave the database empty or corrupt.
+    None,
+}
+
+// fsync the directory holding `path` so a rename into it survives a crash.
+// Windows has no portable way to do this; NTFS journals the rename itself.
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn staging_path(target: &Path, strategy: TempStrategy) -> PathBuf {
+    match strategy {
+        TempStrategy::SameDir => temp_path_for(target),
+        TempStrategy::SystemTemp => {
+            let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+            std::env::temp_dir().join(format!("brewcode-{}-{}.tmp", std::process::id(), n))
+        }
+    }
+}
+
+// Temp file next to the target, e.g. `brewcode.db.tmp-<pid>-<n>`. It must live
+// in the same directory so the final rename stays on one filesystem.
+fn temp_path_for(target: &Path) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "brewcode.db".to_string());
+    let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    target.with_file_name(format!("{}.tmp-{}-{}", file_name, std::process::id(), n))
+}
+
+// Write `data` to `target` without ever leaving a truncated file behind: the
+// bytes go to a temp file first, are fsynced, and only then renamed over the
+// target. If anything fails the original file is untouched.
+pub fn atomic_write(target: &Path, data: &[u8]) -> io::Result<()> {
+    atomic_write_cancellable(
+        target,
+        data,
+        TempStrategy::SameDir,
+        Durability::Full,
+        &AtomicBool::new(false),
+        |_| {},
+    )
+}
+
+// `atomic_write` that gives up before the rename once `cancelled` is set.
+// A write that was stuck in the kernel then removes its temp file instead of
+// replacing the target long after the caller stopped waiting. `progress` is
+// called with the running byte count after each chunk.
+pub fn atomic_write_cancellable(
+    target: &Path,
+    data: &[u8],
+    strategy: TempStrategy,
+    durability: Durability,
+    cancelled: &AtomicBool,
+    progress: impl FnMut(usize),
+) -> io::Result<()> {
+    atomic_write_staged(target, data, strategy, durability, cancelled, progress, |path| {
+        File::create(path)
+    })
+}
+
+// The temp file a write is staged in: a `File`, or in tests a writer that
+// fails partway
+trait StagedFile: Write {
+    fn sync_all(&self) -> io::Result<()>;
+}
+
+impl StagedFile for File {
+    fn sync_all(&self) -> io::Result<()> {
+        File::sync_all(self)
+    }
+}
+
+// `atomic_write_cancellable` with the temp file opened by `create`
+fn atomic_write_staged<F: StagedFile>(
+    target: &Path,
+    data: &[u8],
+    strategy: TempStrategy,
+    durability: Durability,
+    cancelled: &AtomicBool,
+    progress: impl FnMut(usize),
+    create: impl FnOnce(&Path) -> io::Result<F>,
+) -> io::Result<()> {
+    let temp = staging_path(target, strategy);
+
+    let result = write_synced(&temp, data, durability, cancelled, progress, create).and_then(|_| {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "write abandoned"));
+        }
+        copy_permissions(target, &temp)?;
+        match strategy {
+            TempStrategy::SameDir => replace(&temp, target, durability)?,
+            // The rename only works when the temp dir shares the target's filesystem
+            TempStrategy::SystemTemp => {
+                fs::rename(&temp, target).or_else(|_| copy_over(&temp, target, durability))?
+            }
+        }
+        match durability {
+            Durability::Full => sync_parent_dir(target),
+            _ => Ok(()),
+        }
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// Like `atomic_write`, for output produced incrementally: `write` fills the
+// temp file, which is then fsynced and renamed over `target`
+pub fn atomic_write_with<T, E: From<io::Error>>(
+    target: &Path,
+    write: impl FnOnce(&mut File) -> Result<T, E>,
+) -> Result<T, E> {
+    let temp = temp_path_for(target);
+
+    let result = File::create(&temp).map_err(E::from).and_then(|mut file| {
+        let value = write(&mut file)?;
+        file.sync_all()?;
+        copy_permissions(target, &temp)?;
+        replace(&temp, target, Durability::Full)?;
+        sync_parent_dir(target)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// Give a staged temp file the permissions of the file it will replace, so
+// saving over a 0600 database doesn't widen it to the umask default. A new
+// target keeps the default.
+fn copy_permissions(target: &Path, temp: &Path) -> io::Result<()> {
+    match fs::metadata(target) {
+        Ok(metadata) => fs::set_permissions(temp, metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_synced<F: StagedFile>(
+    path: &Path,
+    data: &[u8],
+    durability: Durability,
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(usize),
+    create: impl FnOnce(&Path) -> io::Result<F>,
+) -> io::Result<()> {
+    let mut file = create(path)?;
+    let mut written = 0;
+    for chunk in data.chunks(WRITE_CHUNK_BYTES) {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "write abandoned"));
+        }
+        file.write_all(chunk)?;
+        written += chunk.len();
+        progress(written);
+    }
+    match durability {
+        Durability::None => Ok(()),
+        _ => file.sync_all(),
+    }
+}
+
+#[cfg(not(windows))]
+fn replace(temp: &Path, target: &Path, _durability: Durability) -> io::Result<()> {
+    fs::rename(temp, target)
+}
+
+// Renaming over an existing file fails on Windows when another process holds
+// it open without delete sharing. Copying over it in place still works there,
+// and the fully written temp file means we never copy a partial buffer.
+#[cfg(windows)]
+fn replace(temp: &Path, target: &Path, durability: Durability) -> io::Result<()> {
+    match fs::rename(temp, target) {
+        Ok(()) => Ok(()),
+        Err(_) if target.exists() => copy_over(temp, target, durability),
+        Err(e) => Err(e),
+    }
+}
+
+// Copy a fully written temp file over `target` in place, fsync it unless
+// `durability` is None, and remove the temp file. Not atomic, but never
+// copies a partial buffer.
+fn copy_over(temp: &Path, target: &Path, durability: Durability) -> io::Result<()> {
+    fs::copy(temp, target)?;
+    if durability != Durability::None {
+        File::options().write(true).open(target)?.sync_all()?;
+    }
+    fs::remove_file(temp)
+}
+
+// Create a new, empty `brewcode-<pid>-<n>.<extension>` file in the OS temp
+// directory. `create_new` guarantees we never reuse an existing name.
+pub fn create_temp_file(extension: &str) -> io::Result<(PathBuf, File)> {
+    let dir = std::env::temp_dir();
+    loop {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("brewcode-{}-{}.{}", std::process::id(), n, extension));
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Write `data` to a new temp file from `create_temp_file`
+pub fn write_temp_file(data: &[u8], extension: &str) -> io::Result<PathBuf> {
+    let (path, mut file) = create_temp_file(extension)?;
+    let written = file.write_all(data).and_then(|_| file.sync_all());
+    if let Err(e) = written {
+        drop(file);
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(path)
+}
+
+// Backup slot `index` for `target`: `.bak`, `.bak.1`, `.bak.2`, ...
+pub fn backup_path(target: &Path, index: u8) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    if index == 0 {
+        name.push(".bak");
+    } else {
+        name.push(format!(".bak.{}", index));
+    }
+    PathBuf::from(name)
+}
+
+// Checksum sidecar for `target`: `<file>.sha256`
+pub fn checksum_sidecar_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+// Record the hex SHA-256 and size of what was just written to `target`
+pub fn write_checksum_sidecar(target: &Path, digest: &str, size: u64) -> io::Result<()> {
+    fs::write(checksum_sidecar_path(target), format!("{} {}\n", digest, size))
+}
+
+// Digest and size from `target`'s sidecar; None if there isn't a readable one
+pub fn read_checksum_sidecar(target: &Path) -> Option<(String, u64)> {
+    let contents = fs::read_to_string(checksum_sidecar_path(target)).ok()?;
+    let mut fields = contents.split_whitespace();
+    let digest = fields.next()?.to_ascii_lowercase();
+    let size = fields.next()?.parse().ok()?;
+    Some((digest, size))
+}
+
+// The first `len` bytes of `path`, or the whole file if it's shorter
+pub fn read_prefix(path: &Path, len: u64) -> io::Result<Vec<u8>> {
+    let mut prefix = Vec::new();
+    File::open(path)?.take(len).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+// Whether `path` is marked read-only; such files are opened but never saved
+// over
+pub fn is_read_only(path: &Path) -> io::Result<bool> {
+    Ok(fs::metadata(path)?.permissions().readonly())
+}
+
+// Copy `source` over `target` atomically, so a reader of `target` never sees
+// half a file
+pub fn copy_atomic(source: &Path, target: &Path) -> io::Result<()> {
+    atomic_write_with(target, |file| {
+        io::copy(&mut File::open(source)?, file)?;
+        Ok(())
+    })
+}
+
+// Copy the current file into `.bak`, shifting existing backups up one slot
+// and dropping the oldest, plus any left beyond `count` after it was lowered.
+// The copy is staged first, so if it fails every backup stays where it was.
+// Does nothing if the target doesn't exist yet.
+pub fn rotate_backups(target: &Path, count: u8) -> io::Result<()> {
+    if count == 0 || !target.exists() {
+        return Ok(());
+    }
+
+    // A copy cut short, e.g. by a full disk, isn't a usable backup
+    let newest = backup_path(target, 0);
+    let staged = temp_path_for(&newest);
+    let result = fs::copy(target, &staged).and_then(|_| {
+        prune_backups(target, count);
+        for index in (1..count).rev() {
+            let older = backup_path(target, index - 1);
+            if older.exists() {
+                fs::rename(&older, backup_path(target, index))?;
+            }
+        }
+        fs::rename(&staged, &newest)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+// Delete `.bak.<n>` backups of `target` with n at or above `count`. Best
+// effort: a backup we can't delete only wastes space.
+fn prune_backups(target: &Path, count: u8) {
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        // The suffix is ASCII, so a lossy name is enough to find the index;
+        // comparing with `backup_path` exactly rules out any other file
+        let index = name
+            .to_string_lossy()
+            .rsplit_once(".bak.")
+            .and_then(|(_, index)| index.parse::<u8>().ok());
+        let Some(index) = index.filter(|&index| index >= count) else {
+            continue;
+        };
+        if backup_path(target, index).file_name() == Some(name.as_os_str()) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+// Which file a path points at: device and inode on unix, volume serial and
+// file index on Windows. Survives edits but not being replaced by another file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileId {
+    pub device: u64,
+    pub index: u64,
+}
+
+#[cfg(unix)]
+pub fn file_id(path: &Path) -> io::Result<Option<FileId>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+    Ok(Some(FileId {
+        device: metadata.dev(),
+        index: metadata.ino(),
+    }))
+}
+
+#[cfg(windows)]
+pub fn file_id(path: &Path) -> io::Result<Option<FileId>> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    let file = File::open(path)?;
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(FileId {
+        device: info.dwVolumeSerialNumber as u64,
+        index: ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64,
+    }))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn file_id(path: &Path) -> io::Result<Option<FileId>> {
+    fs::metadata(path).map(|_| None)
+}
+
+// What we last saw on disk, used to notice edits made by other programs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub id: Option<FileId>,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            id: file_id(path)?,
+        })
+    }
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+pub fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+// Decompress `data`, failing once the output passes `limit` bytes so a small
+// gzip bomb can't fill memory
+pub fn gunzip(data: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decoded)?;
+    if decoded.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("it decompresses to more than {} bytes", limit),
+        ));
+    }
+    Ok(decoded)
+}
+
+// Hex-encoded SHA-256 of `data`, hashed straight from the borrowed slice
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Hex-encoded SHA-256 of a file, read in chunks so large files aren't loaded whole
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Leftovers brewcode writes next to a database, told apart by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum StrayKind {
+    // `<name>.tmp-<pid>-<n>` from an atomic write that never finished
+    Temp,
+    // `<name>.bak` and `<name>.bak.<n>` from `rotate_backups`
+    Backup,
+    // `<name>.lock` from `lock::acquire`
+    Lock,
+}
+
+fn all_digits(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+// What `file_name` is if it follows one of our naming conventions, and the
+// name of the database it belongs to
+pub fn stray_kind(file_name: &str) -> Option<(StrayKind, &str)> {
+    let (kind, base) = if let Some((base, suffix)) = file_name.rsplit_once(".tmp-") {
+        let (pid, n) = suffix.split_once('-')?;
+        if !all_digits(pid) || !all_digits(n) {
+            return None;
+        }
+        (StrayKind::Temp, base)
+    } else if let Some(base) = file_name.strip_suffix(".bak") {
+        (StrayKind::Backup, base)
+    } else if let Some((base, index)) = file_name.rsplit_once(".bak.") {
+        if !all_digits(index) {
+            return None;
+        }
+        (StrayKind::Backup, base)
+    } else {
+        (StrayKind::Lock, file_name.strip_suffix(".lock")?)
+    };
+    (!base.is_empty()).then_some((kind, base))
+}
+
+// Id of the process that started writing a `StrayKind::Temp` file
+pub fn temp_file_pid(file_name: &str) -> Option<u32> {
+    file_name.rsplit_once(".tmp-")?.1.split_once('-')?.0.parse().ok()
+}
+
+// First free `<stem> copy.<ext>`, `<stem> copy 2.<ext>`, ... next to `path`
+pub fn next_copy_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+
+    let mut n = 1;
+    loop {
+        let mut name = stem.to_os_string();
+        name.push(" copy");
+        if n > 1 {
+            name.push(format!(" {}", n));
+        }
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        let candidate = path.with_file_name(name);
+        if !candidate.exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+// Marks a `path_key` holding base64 OS bytes. No real path contains NUL, so
+// no path is its own key and also starts with this.
+const OS_KEY_PREFIX: &str = "\0os:";
+
+// A string for `path` that `path_from_key` turns back into exactly the same
+// path, for handing to the frontend and keying config files. Unicode paths are
+// their own key; anything else is encoded rather than lossily converted.
+pub fn path_key(path: &Path) -> String {
+    match path.to_str() {
+        Some(text) => text.to_string(),
+        None => {
+            let bytes = os_bytes(path.as_os_str());
+            let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+            format!("{}{}", OS_KEY_PREFIX, encoded)
+        }
+    }
+}
+
+// The path `key` came from. Anything that isn't an encoded key is a plain path.
+pub fn path_from_key(key: &str) -> PathBuf {
+    key.strip_prefix(OS_KEY_PREFIX)
+        .and_then(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded).ok())
+        .and_then(os_string_from_bytes)
+        .map_or_else(|| PathBuf::from(key), PathBuf::from)
+}
+
+#[cfg(unix)]
+fn os_bytes(text: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    text.as_bytes().to_vec()
+}
+
+#[cfg(windows)]
+fn os_bytes(text: &OsStr) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    text.encode_wide().flat_map(u16::to_le_bytes).collect()
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+// Windows paths are UTF-16, possibly with unpaired surrogates
+#[cfg(windows)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::windows::ffi::OsStringExt;
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Some(OsString::from_wide(&wide))
+}
+
+// Well-known OS folders no database should be saved into
+pub fn system_dirs() -> Vec<PathBuf> {
+    #[cfg(windows)]
+    let dirs: Vec<PathBuf> = ["SystemRoot", "ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+        .iter()
+        .filter_map(|var| std::env::var_os(var))
+        .map(PathBuf::from)
+        .collect();
+    #[cfg(not(windows))]
+    let dirs: Vec<PathBuf> = [
+        "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys",
+        "/usr", "/System", "/Library", "/private/etc",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    dirs
+}
+
+// Canonical form of `path`, resolving the deepest ancestor that exists so
+// paths to files that haven't been created yet still compare correctly
+fn resolve_existing(path: &Path) -> PathBuf {
+    let mut rest = Vec::new();
+    let mut current = path;
+    loop {
+        if let Ok(resolved) = fs::canonicalize(current) {
+            return rest.iter().rev().fold(resolved, |acc, part| acc.join(part));
+        }
+        match (current.parent(), current.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                current = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+// The first of `dirs` that contains `path`, after resolving symlinks on both
+pub fn containing_dir<'a>(path: &Path, dirs: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    let path = resolve_existing(path);
+    dirs.iter().find(|dir| path.starts_with(resolve_existing(dir)))
+}
+
+// Free bytes on the volume that `target` would be written to. `None` when the
+// platform can't tell us, in which case callers should not block the write.
+pub fn available_space(target: &Path) -> Option<u64> {
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs4::available_space(dir).ok()
+}
+
+// Whether we could save to `target`: probes its folder by creating and
+// removing a temp file, since permissions alone miss ACLs and read-only
+// mounts. A directory counts as a folder to save into.
+pub fn can_write_to(target: &Path) -> bool {
+    if target.is_dir() {
+        return can_write_to(&target.join("brewcode.db"));
+    }
+    if fs::metadata(target).is_ok_and(|metadata| metadata.permissions().readonly()) {
+        return false;
+    }
+
+    let probe = temp_path_for(target);
+    let Ok(mut file) = File::options().write(true).create_new(true).open(&probe) else {
+        return false;
+    };
+    // A full disk or quota can allow the create but refuse the data
+    let written = file.write_all(b"brewcode").is_ok();
+    drop(file);
+    let removed = fs::remove_file(&probe).is_ok();
+    written && removed
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::error::BrewError;
+
+    // A fresh, empty folder under the OS temp directory
+    pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "brewcode-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            n
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Temp files left next to `target`
+    fn leftover_temps(target: &Path) -> Vec<PathBuf> {
+        fs::read_dir(target.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path != target)
+            .collect()
+    }
+
+    // Writes through to a real file until `budget` bytes are in, then fails
+    // with `error`
+    struct FailingFile {
+        file: File,
+        budget: usize,
+        error: fn() -> io::Error,
+    }
+
+    impl Write for FailingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.budget == 0 {
+                return Err((self.error)());
+            }
+            let n = self.file.write(&buf[..buf.len().min(self.budget)])?;
+            self.budget -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl StagedFile for FailingFile {
+        fn sync_all(&self) -> io::Result<()> {
+            self.file.sync_all()
+        }
+    }
+
+    fn write_failing(
+        target: &Path,
+        data: &[u8],
+        budget: usize,
+        error: fn() -> io::Error,
+    ) -> io::Result<()> {
+        atomic_write_staged(
+            target,
+            data,
+            TempStrategy::SameDir,
+            Durability::Full,
+            &AtomicBool::new(false),
+            |_| {},
+            |path| {
+                Ok(FailingFile {
+                    file: File::create(path)?,
+                    budget,
+                    error,
+                })
+            },
+        )
+    }
+
+    #[test]
+    fn interrupted_write_keeps_original_and_removes_temp() {
+        let dir = scratch_dir("interrupted");
+        let target = dir.join("brewcode.db");
+        fs::write(&target, b"original").unwrap();
+
+        let data = vec![7u8; 2 * WRITE_CHUNK_BYTES + 10];
+        let interrupted = || io::Error::new(io::ErrorKind::BrokenPipe, "device went away");
+        let result = write_failing(&target, &data, WRITE_CHUNK_BYTES + 5, interrupted);
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&target).unwrap(), b"original");
+        let temp_suffix = format!(".tmp-{}-", std::process::id());
+        assert!(!leftover_temps(&target)
+            .iter()
+            .any(|path| path.to_string_lossy().contains(&temp_suffix)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn containing_dir_matches_whole_components() {
+        let root = scratch_dir("forbidden");
+        let config = root.join("cfg");
+        fs::create_dir_all(config.join("nested")).unwrap();
+        fs::create_dir_all(root.join("cfg-other")).unwrap();
+        let dirs = vec![config.clone()];
+
+        assert_eq!(containing_dir(&config.join("brewcode.db"), &dirs), Some(&config));
+        assert_eq!(containing_dir(&config.join("nested/brewcode.db"), &dirs), Some(&config));
+        assert_eq!(containing_dir(&root.join("cfg-other/brewcode.db"), &dirs), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn gunzip_stops_at_the_limit() {
+        let data = vec![0u8; 1024 * 1024];
+        let compressed = gzip(&data).unwrap();
+
+        let err = gunzip(&compressed, 1000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(gunzip(&compressed, data.len() as u64).unwrap(), data);
+    }
+
+    #[cfg(unix)]
+    fn disk_full() -> io::Error {
+        io::Error::from_raw_os_error(28)
+    }
+
+    #[cfg(windows)]
+    fn disk_full() -> io::Error {
+        io::Error::from_raw_os_error(112)
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn disk_full_keeps_original_and_removes_temp() {
+        let dir = scratch_dir("disk-full");
+        let target = dir.join("brewcode.db");
+        fs::write(&target, b"original").unwrap();
+
+        let data = vec![7u8; WRITE_CHUNK_BYTES + 1024];
+        let err = write_failing(&target, &data, WRITE_CHUNK_BYTES / 2, disk_full).unwrap_err();
+
+        assert!(matches!(BrewError::from(err), BrewError::DiskFull));
+        assert_eq!(fs::read(&target).unwrap(), b"original");
+        assert!(leftover_temps(&target).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn path_keys_round_trip() {
+        let plain = Path::new("/data/brews 2024/ä.db");
+        assert_eq!(path_key(plain), "/data/brews 2024/ä.db");
+        assert_eq!(path_from_key(&path_key(plain)), plain);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let odd = Path::new(OsStr::from_bytes(b"/data/caf\xe9.db"));
+            let key = path_key(odd);
+            assert!(key.starts_with(OS_KEY_PREFIX));
+            assert_eq!(path_from_key(&key), odd);
+            // The lossy form would name a different file
+            assert_ne!(PathBuf::from(odd.to_string_lossy().to_string()), odd);
+
+            let dir = scratch_dir("copy-key");
+            let copy = next_copy_path(&dir.join(OsStr::from_bytes(b"caf\xe9.db")));
+            assert_eq!(copy.file_name().unwrap().as_bytes(), b"caf\xe9 copy.db");
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replacing_keeps_the_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("mode");
+        let target = dir.join("private.db");
+        fs::write(&target, b"old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+
+        atomic_write(&target, b"new").unwrap();
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+        atomic_write_with(&target, |file| file.write_all(b"newer")).unwrap();
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&target).unwrap(), b"newer");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_drops_backups_beyond_the_count() {
+        let dir = scratch_dir("rotate");
+        let target = dir.join("brewcode.db");
+        fs::write(&target, b"current").unwrap();
+        fs::write(backup_path(&target, 0), b"previous").unwrap();
+        for index in 1..=5 {
+            fs::write(backup_path(&target, index), b"older").unwrap();
+        }
+        fs::write(dir.join("other.db.bak.7"), b"someone else's").unwrap();
+
+        rotate_backups(&target, 2).unwrap();
+        assert_eq!(fs::read(backup_path(&target, 0)).unwrap(), b"current");
+        assert_eq!(fs::read(backup_path(&target, 1)).unwrap(), b"previous");
+        for index in 2..=5 {
+            assert!(!backup_path(&target, index).exists());
+        }
+        assert!(dir.join("other.db.bak.7").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_backup_copy_keeps_every_generation() {
+        let dir = scratch_dir("rotate-fail");
+        // Copying a directory fails after the target check passes
+        let target = dir.join("brewcode.db");
+        fs::create_dir(&target).unwrap();
+        fs::write(backup_path(&target, 0), b"previous").unwrap();
+
+        assert!(rotate_backups(&target, 3).is_err());
+        assert_eq!(fs::read(backup_path(&target, 0)).unwrap(), b"previous");
+        assert!(!backup_path(&target, 1).exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
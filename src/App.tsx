@@ -80,6 +80,10 @@ function App() {
         locateFile: (file: string) => `/sql-wasm/${file}`
       });
       const loadedDb = await openDatabase(SQL);
+      if (!loadedDb) {
+        setIsLoading(false);
+        return;
+      }
       setDb(loadedDb);
       setLastSaved(new Date());
       // Path will be set by openDatabase through the Rust backend
@@ -2,12 +2,74 @@ import { invoke } from '@tauri-apps/api/core';
 
 export interface SaveResponse {
   success: boolean;
-  path?: string;
+  // Exact path to pass back to commands; null if it isn't valid Unicode
+  path?: string | null;
+  // Path for showing to the user
+  path_display?: string | null;
+  warning?: string;
+  bytes_written?: number | null;
+  // True when the save made a new file instead of overwriting one
+  created_new?: boolean;
+  // Whether the save was also copied to the mirror folder; null without one
+  mirror_written?: boolean | null;
   error?: string;
+  errorKind?: string;
+}
+
+export interface OpenResponse {
+  data: number[];
+  path: string | null;
+  path_display: string;
+  size: number;
+  was_compressed: boolean;
+  was_encrypted: boolean;
+  checksum_mismatch: boolean;
+}
+
+/**
+ * Error shape returned by every backend command.
+ * `kind` is stable (e.g. "cancelled", "io", "no_path_set").
+ */
+export interface BrewError {
+  kind: string;
+  message: string;
+  // Set when kind is "insufficient_space"
+  required?: number;
+  available?: number;
+  // Set when kind is "locked_by_other"
+  pid?: number;
+  since?: number | null;
+  // Set when kind is "wrong_passphrase" and a file was being opened
+  path?: string | null;
+}
+
+export function isBrewError(error: unknown): error is BrewError {
+  return typeof error === 'object' && error !== null && 'kind' in error && 'message' in error;
+}
+
+/**
+ * True when the user dismissed a dialog; callers should ignore it silently.
+ */
+export function isCancelled(error: unknown): boolean {
+  return isBrewError(error) && error.kind === 'cancelled';
+}
+
+function errorMessage(error: unknown): string {
+  if (isBrewError(error)) return error.message;
+  return error instanceof Error ? error.message : String(error);
+}
+
+function failedSave(error: unknown): SaveResponse {
+  return {
+    success: false,
+    error: errorMessage(error),
+    errorKind: isBrewError(error) ? error.kind : undefined,
+  };
 }
 
 export interface DbPathResponse {
-  path?: string;
+  path?: string | null;
+  path_display?: string | null;
 }
 
 /**
@@ -22,66 +84,59 @@ export async function saveDatabaseAs(db: any): Promise<SaveResponse> {
     const response = await invoke<SaveResponse>('save_database_as', { data });
     return response;
   } catch (error) {
-    return {
-      success: false,
-      error: error instanceof Error ? error.message : String(error),
-    };
+    return failedSave(error);
   }
 }
 
 /**
  * Saves the database to the current path (no dialog).
- * Returns error if no current path is set.
+ * Returns error if no current path is set. Pass `force` to overwrite after a
+ * `file_identity_changed` error.
  */
-export async function saveDatabase(db: any): Promise<SaveResponse> {
+export async function saveDatabase(db: any, force = false): Promise<SaveResponse> {
   try {
     const binaryArray = db.export();
     const data = Array.from(binaryArray);
     
-    const response = await invoke<SaveResponse>('save_database', { data });
+    const response = await invoke<SaveResponse>('save_database', { data, force });
     return response;
   } catch (error) {
-    return {
-      success: false,
-      error: error instanceof Error ? error.message : String(error),
-    };
+    return failedSave(error);
   }
 }
 
 /**
  * Opens a file picker and loads the selected database.
  * This becomes the new "current" database path.
+ * Resolves to null if the user dismisses the picker.
  */
-export async function openDatabase(SQL: any): Promise<any> {
+export async function openDatabase(SQL: any): Promise<any | null> {
   try {
-    const data = await invoke<number[]>('open_database');
-    const uint8Array = new Uint8Array(data);
+    const response = await invoke<OpenResponse>('open_database', { force: false });
+    const uint8Array = new Uint8Array(response.data);
     const db = new SQL.Database(uint8Array);
     
     return db;
   } catch (error) {
-    throw new Error(
-      error instanceof Error ? error.message : String(error)
-    );
+    if (isCancelled(error)) return null;
+    throw new Error(errorMessage(error));
   }
 }
 
 /**
  * Exports a copy of the database to a chosen location.
  * Does NOT change the current database path.
+ * Pass `compress` to write a gzipped `.db.gz` instead.
  */
-export async function exportDatabase(db: any): Promise<SaveResponse> {
+export async function exportDatabase(db: any, compress = false): Promise<SaveResponse> {
   try {
     const binaryArray = db.export();
     const data = Array.from(binaryArray);
     
-    const response = await invoke<SaveResponse>('export_database', { data });
+    const response = await invoke<SaveResponse>('export_database', { data, compress });
     return response;
   } catch (error) {
-    return {
-      success: false,
-      error: error instanceof Error ? error.message : String(error),
-    };
+    return failedSave(error);
   }
 }
 
@@ -91,7 +146,7 @@ export async function exportDatabase(db: any): Promise<SaveResponse> {
 export async function getCurrentDbPath(): Promise<string | null> {
   try {
     const response = await invoke<DbPathResponse>('get_current_db_path');
-    return response.path || null;
+    return response.path_display || null;
   } catch (error) {
     console.error('Failed to get current DB path:', error);
     return null;